@@ -0,0 +1,131 @@
+use crate::Computer;
+use crate::memory::Memory;
+
+pub(crate) const AUDIO_REGISTERS_SIZE: u32 = 24;
+
+// registers
+const ENABLE: u32 = 0;
+const INTERRUPT_ENABLE: u32 = 1;
+const STATUS: u32 = 2; // buffer low, cleared by the guest
+const CHANNELS: u32 = 3;
+const SAMPLE_RATE: u32 = 4;
+const BUFFER_SIZE: u32 = 8; // in samples
+const WRITE_POSITION: u32 = 12; // next sample the guest will write
+const PLAY_POSITION: u32 = 16; // next sample the device will play
+const LOW_THRESHOLD: u32 = 20; // buffer low when fewer samples are queued
+
+// produces frames of interleaved signed 16-bit samples at the sample rate of emulated time
+pub(crate) struct Audio
+{
+    sample_rate: u64,
+    channels: u32,
+    buffer_size: u32,
+    cycles_per_second: u64,
+    phase: u64,
+    output: Vec<i16>,
+}
+
+impl Audio
+{
+    pub(crate) fn new(sample_rate: u32, channels: u32, buffer_size: u32, cycles_per_second: u64) -> Audio
+    {
+        Audio
+        {
+            sample_rate: sample_rate as u64,
+            channels,
+            buffer_size,
+            cycles_per_second,
+            phase: 0,
+            output: Vec::new(),
+        }
+    }
+
+    // true when the next frame is due, called once per cycle
+    fn tick(&mut self) -> bool
+    {
+        self.phase += self.sample_rate;
+        if self.phase < self.cycles_per_second
+        {
+            return false;
+        }
+        self.phase -= self.cycles_per_second;
+        true
+    }
+}
+
+impl Computer // audio
+{
+    pub(crate) fn init_audio_registers(memory: &mut Memory, audio: &Audio)
+    {
+        let base = memory.audio_buffer_address();
+        memory.write_byte((base + CHANNELS) as usize, audio.channels as u8);
+        memory.write_word((base + SAMPLE_RATE) as usize, audio.sample_rate as u32);
+        memory.write_word((base + BUFFER_SIZE) as usize, audio.buffer_size);
+        memory.write_word((base + LOW_THRESHOLD) as usize, audio.buffer_size / 4);
+    }
+
+    fn audio_byte(&self, register: u32) -> u8
+    {
+        self.memory.read_byte((self.memory.audio_buffer_address() + register) as usize)
+    }
+
+    fn audio_word(&self, register: u32) -> u32
+    {
+        self.memory.read_word((self.memory.audio_buffer_address() + register) as usize)
+    }
+
+    fn audio_sample(&self, index: u32) -> i16
+    {
+        let bytes = self.memory.bytes(self.memory.audio_samples_address() + 2 * index, 2);
+        i16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    pub(crate) fn audio_controller(&mut self)
+    {
+        if !self.audio.tick()
+        {
+            return;
+        }
+
+        let channels = self.audio.channels;
+        let size = self.audio.buffer_size;
+        let play = self.audio_word(PLAY_POSITION) % size;
+        let write = self.audio_word(WRITE_POSITION) % size;
+        let queued = (write + size - play) % size;
+
+        // disabled or underrun plays silence, the output keeps running either way
+        let mut frame = [0i16; 2];
+        if self.audio_byte(ENABLE) != 0 && queued >= channels
+        {
+            for channel in 0..channels
+            {
+                frame[channel as usize] = self.audio_sample((play + channel) % size);
+            }
+
+            let base = self.memory.audio_buffer_address();
+            self.memory.write_word((base + PLAY_POSITION) as usize, (play + channels) % size);
+
+            if queued - channels < self.audio_word(LOW_THRESHOLD)
+            {
+                self.memory.write_byte((base + STATUS) as usize, 1);
+            }
+        }
+
+        let tone = self.psg_sample();
+        for channel in 0..channels
+        {
+            self.audio.output.push(frame[channel as usize].saturating_add(tone));
+        }
+    }
+
+    pub(crate) fn audio_interrupt(&self) -> bool
+    {
+        self.audio_byte(STATUS) != 0 && self.audio_byte(INTERRUPT_ENABLE) != 0
+    }
+
+    // samples produced since the last call, interleaved by channel
+    pub fn take_audio_samples(&mut self) -> Vec<i16>
+    {
+        std::mem::take(&mut self.audio.output)
+    }
+}
@@ -0,0 +1,305 @@
+use crate::Computer;
+
+pub(crate) const BLITTER_BUFFER_SIZE: u32 = 40;
+
+// registers
+const COMMAND: u32 = 0;
+const STATUS: u32 = 1;
+const INTERRUPT_ENABLE: u32 = 2;
+const BYTES_PER_PIXEL: u32 = 3;
+const SOURCE: u32 = 4;
+const DESTINATION: u32 = 8;
+const SOURCE_STRIDE: u32 = 12;
+const DESTINATION_STRIDE: u32 = 16;
+const WIDTH: u32 = 20;
+const HEIGHT: u32 = 24;
+const COLOR: u32 = 28; // fill colour or colour key
+const LINE: u32 = 32; // x0, y0, x1, y1 as signed halves
+
+// commands
+const IDLE: u8 = 0;
+const FILL: u8 = 1;
+const COPY: u8 = 2;
+const COPY_TRANSPARENT: u8 = 3;
+const DRAW_LINE: u8 = 4;
+
+impl Computer // blitter
+{
+    fn blitter_byte(&self, register: u32) -> u8
+    {
+        self.memory.read_byte((self.memory.blitter_buffer_address() + register) as usize)
+    }
+
+    fn blitter_half(&self, register: u32) -> i16
+    {
+        let address = (self.memory.blitter_buffer_address() + register) as usize;
+        let high = self.memory.read_byte(address) as u16;
+        let low = self.memory.read_byte(address + 1) as u16;
+        ((high << 8) | low) as i16
+    }
+
+    fn blitter_word(&self, register: u32) -> u32
+    {
+        self.memory.read_word((self.memory.blitter_buffer_address() + register) as usize)
+    }
+
+    // colour register as bytes in memory order
+    fn blitter_color(&self, bpp: u32) -> Vec<u8>
+    {
+        let color = self.blitter_word(COLOR);
+        (0..bpp).rev().map(|i| (color >> (8 * i)) as u8).collect()
+    }
+
+    pub(crate) fn blitter_controller(&mut self)
+    {
+        let command = self.blitter_byte(COMMAND);
+        if command == IDLE
+        {
+            return;
+        }
+
+        let bpp = self.blitter_byte(BYTES_PER_PIXEL) as u32;
+        if !(1..=4).contains(&bpp)
+        {
+            panic!("Bad blitter pixel size");
+        }
+
+        match command
+        {
+            FILL => self.blit_fill(bpp),
+            COPY => self.blit_copy(bpp),
+            COPY_TRANSPARENT => self.blit_copy_transparent(bpp),
+            DRAW_LINE => self.blit_line(bpp),
+            _ => panic!("Bad blitter command"),
+        }
+
+        let base = self.memory.blitter_buffer_address();
+        self.memory.write_byte((base + COMMAND) as usize, IDLE);
+        self.memory.write_byte((base + STATUS) as usize, 1); // done, cleared by the guest
+    }
+
+    pub(crate) fn blitter_interrupt(&self) -> bool
+    {
+        self.blitter_byte(STATUS) != 0 && self.blitter_byte(INTERRUPT_ENABLE) != 0
+    }
+
+    // whether rows of len bytes, stride bytes apart, all lie inside memory
+    // blits that don't are dropped, so the row addresses below can't overflow
+    fn blit_fits(&self, address: u32, stride: u32, height: u32, len: u64) -> bool
+    {
+        let size = self.memory.size() as u64;
+        let last_row = height.saturating_sub(1) as u64 * stride as u64;
+        len <= size && last_row.checked_add(address as u64 + len).is_some_and(|end| end <= size)
+    }
+
+    fn blit_fill(&mut self, bpp: u32)
+    {
+        let destination = self.blitter_word(DESTINATION);
+        let stride = self.blitter_word(DESTINATION_STRIDE);
+        let (width, height) = (self.blitter_word(WIDTH), self.blitter_word(HEIGHT));
+        if !self.blit_fits(destination, stride, height, width as u64 * bpp as u64)
+        {
+            return;
+        }
+
+        let row: Vec<u8> = self.blitter_color(bpp).repeat(width as usize);
+        for y in 0..height
+        {
+            self.memory.write_bytes(destination + y * stride, &row);
+        }
+    }
+
+    fn blit_copy(&mut self, bpp: u32)
+    {
+        let (source, destination) = (self.blitter_word(SOURCE), self.blitter_word(DESTINATION));
+        let (source_stride, destination_stride) =
+            (self.blitter_word(SOURCE_STRIDE), self.blitter_word(DESTINATION_STRIDE));
+        let (width, height) = (self.blitter_word(WIDTH), self.blitter_word(HEIGHT));
+        let len = width as u64 * bpp as u64;
+        if !self.blit_fits(source, source_stride, height, len)
+            || !self.blit_fits(destination, destination_stride, height, len)
+        {
+            return;
+        }
+
+        // copy rows bottom up when moving down, so overlapping rows are read before they are overwritten
+        let rows: Vec<u32> = if destination > source { (0..height).rev().collect() } else { (0..height).collect() };
+        for y in rows
+        {
+            self.memory.copy(source + y * source_stride, destination + y * destination_stride, width * bpp);
+        }
+    }
+
+    fn blit_copy_transparent(&mut self, bpp: u32)
+    {
+        let (source, destination) = (self.blitter_word(SOURCE), self.blitter_word(DESTINATION));
+        let (source_stride, destination_stride) =
+            (self.blitter_word(SOURCE_STRIDE), self.blitter_word(DESTINATION_STRIDE));
+        let (width, height) = (self.blitter_word(WIDTH), self.blitter_word(HEIGHT));
+        let len = width as u64 * bpp as u64;
+        if !self.blit_fits(source, source_stride, height, len)
+            || !self.blit_fits(destination, destination_stride, height, len)
+        {
+            return;
+        }
+        let key = self.blitter_color(bpp);
+
+        let rows: Vec<u32> = if destination > source { (0..height).rev().collect() } else { (0..height).collect() };
+        for y in rows
+        {
+            let row = self.memory.bytes(source + y * source_stride, width * bpp).to_vec();
+            for (x, pixel) in row.chunks_exact(bpp as usize).enumerate()
+            {
+                if pixel != key.as_slice()
+                {
+                    self.memory.write_bytes(destination + y * destination_stride + x as u32 * bpp, pixel);
+                }
+            }
+        }
+    }
+
+    fn blit_line(&mut self, bpp: u32)
+    {
+        let destination = self.blitter_word(DESTINATION);
+        let stride = self.blitter_word(DESTINATION_STRIDE);
+        let color = self.blitter_color(bpp);
+
+        let (mut x, mut y) = (self.blitter_half(LINE) as i32, self.blitter_half(LINE + 2) as i32);
+        let (x1, y1) = (self.blitter_half(LINE + 4) as i32, self.blitter_half(LINE + 6) as i32);
+
+        // Bresenham, points left or above the destination are clipped
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (step_x, step_y) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+        let mut error = dx + dy;
+        loop
+        {
+            if x >= 0 && y >= 0 && self.blit_fits(destination, stride, y as u32 + 1, (x as u32 * bpp + bpp) as u64)
+            {
+                self.memory.write_bytes(destination + y as u32 * stride + x as u32 * bpp, &color);
+            }
+            if x == x1 && y == y1
+            {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= dy
+            {
+                error += dy;
+                x += step_x;
+            }
+            if error2 <= dx
+            {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // starts a command on a 16 byte wide area at the start of VRAM, one byte per pixel
+    fn blit(computer: &mut Computer, command: u8, registers: &[(u32, u32)])
+    {
+        let base = computer.memory.blitter_buffer_address();
+        computer.memory.write_byte((base + BYTES_PER_PIXEL) as usize, 1);
+        computer.memory.write_word((base + SOURCE_STRIDE) as usize, 16);
+        computer.memory.write_word((base + DESTINATION_STRIDE) as usize, 16);
+        for &(register, value) in registers
+        {
+            computer.memory.write_word((base + register) as usize, value);
+        }
+        computer.memory.write_byte((base + COMMAND) as usize, command);
+        computer.blitter_controller();
+    }
+
+    fn rows(computer: &Computer, count: u32) -> Vec<Vec<u8>>
+    {
+        let vram = computer.memory.vram_address();
+        (0..count).map(|y| computer.memory.bytes(vram + 16 * y, 8).to_vec()).collect()
+    }
+
+    #[test]
+    fn fill_stays_inside_the_rectangle()
+    {
+        let mut computer = Computer::with_rom(&[], &[]);
+        let vram = computer.memory.vram_address();
+        blit(&mut computer, FILL, &[(DESTINATION, vram + 16 + 1), (WIDTH, 3), (HEIGHT, 2), (COLOR, 7)]);
+        assert_eq!(rows(&computer, 4), [
+            [0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 7, 7, 7, 0, 0, 0, 0],
+            [0, 7, 7, 7, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+        ]);
+        assert_eq!(computer.blitter_byte(COMMAND), IDLE);
+        assert_eq!(computer.blitter_byte(STATUS), 1);
+    }
+
+    #[test]
+    fn overlapping_copies_read_rows_before_overwriting_them()
+    {
+        let mut computer = Computer::with_rom(&[], &[]);
+        let vram = computer.memory.vram_address();
+        for y in 0..3
+        {
+            computer.memory.write_bytes(vram + 16 * y, &[y as u8 + 1; 2]);
+        }
+
+        blit(&mut computer, COPY, &[(SOURCE, vram), (DESTINATION, vram + 16 + 1), (WIDTH, 2), (HEIGHT, 3)]);
+        assert_eq!(rows(&computer, 4), [
+            [1, 1, 0, 0, 0, 0, 0, 0],
+            [2, 1, 1, 0, 0, 0, 0, 0],
+            [3, 2, 2, 0, 0, 0, 0, 0],
+            [0, 3, 3, 0, 0, 0, 0, 0],
+        ]);
+
+        blit(&mut computer, COPY, &[(SOURCE, vram + 16 + 1), (DESTINATION, vram), (WIDTH, 2), (HEIGHT, 3)]);
+        assert_eq!(rows(&computer, 4), [
+            [1, 1, 0, 0, 0, 0, 0, 0],
+            [2, 2, 1, 0, 0, 0, 0, 0],
+            [3, 3, 2, 0, 0, 0, 0, 0],
+            [0, 3, 3, 0, 0, 0, 0, 0],
+        ]);
+    }
+
+    #[test]
+    fn fill_past_the_end_of_memory_is_dropped()
+    {
+        let mut computer = Computer::with_rom(&[], &[]);
+        let end = computer.memory.size();
+        blit(&mut computer, FILL, &[(DESTINATION, end - 2), (WIDTH, 4), (HEIGHT, 1), (COLOR, 7)]);
+        assert_eq!(computer.memory.bytes(end - 2, 2), [0, 0]);
+        assert_eq!(computer.blitter_byte(STATUS), 1);
+    }
+
+    #[test]
+    fn huge_strides_and_widths_are_dropped()
+    {
+        let mut computer = Computer::with_rom(&[], &[]);
+        let vram = computer.memory.vram_address();
+        blit(&mut computer, FILL, &[(DESTINATION, vram), (DESTINATION_STRIDE, u32::MAX), (WIDTH, 2), (HEIGHT, 2),
+                                    (COLOR, 7)]);
+        blit(&mut computer, FILL, &[(DESTINATION, vram), (WIDTH, u32::MAX), (HEIGHT, 1), (COLOR, 7)]);
+        blit(&mut computer, COPY_TRANSPARENT, &[(SOURCE, vram), (DESTINATION, vram + 16), (SOURCE_STRIDE, u32::MAX),
+                                                (WIDTH, 2), (HEIGHT, 3)]);
+        blit(&mut computer, DRAW_LINE, &[(DESTINATION, vram), (DESTINATION_STRIDE, u32::MAX), (LINE, 0),
+                                         (LINE + 4, 0x0001_0001), (COLOR, 7)]);
+        assert_eq!(rows(&computer, 2), [[7, 0, 0, 0, 0, 0, 0, 0], [0; 8]]);
+    }
+
+    #[test]
+    fn transparent_copy_skips_the_colour_key()
+    {
+        let mut computer = Computer::with_rom(&[], &[]);
+        let vram = computer.memory.vram_address();
+        computer.memory.write_bytes(vram, &[1, 5, 2]);
+        computer.memory.write_bytes(vram + 16, &[9; 8]);
+
+        blit(&mut computer, COPY_TRANSPARENT, &[(SOURCE, vram), (DESTINATION, vram + 16), (WIDTH, 3), (HEIGHT, 1),
+                                                (COLOR, 5)]);
+        assert_eq!(rows(&computer, 2)[1], [1, 9, 2, 9, 9, 9, 9, 9]);
+    }
+}
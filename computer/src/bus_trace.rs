@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::cpu_aux::TransferType;
+use crate::symbols::Symbols;
+
+type Transfer = (u64, bool, TransferType, u32, u32); // cycle, fetch, type, address, data
+
+// last transfers on the bus; shared, so it can still be dumped from a panic hook
+#[derive(Clone)]
+pub struct BusTrace
+{
+    entries: Arc<Mutex<VecDeque<Transfer>>>,
+    capacity: usize,
+    symbols: Symbols,
+}
+
+impl BusTrace
+{
+    pub(crate) fn new(capacity: u32, symbols: &Symbols) -> BusTrace
+    {
+        BusTrace
+        {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity as usize))),
+            capacity: capacity as usize,
+            symbols: symbols.clone(),
+        }
+    }
+
+    pub(crate) fn record(&self, cycle: u64, fetch: bool, transfer_type: TransferType, address: u32, data: u32)
+    {
+        let mut entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if entries.len() == self.capacity
+        {
+            entries.pop_front();
+        }
+        entries.push_back((cycle, fetch, transfer_type, address, data));
+    }
+
+    // oldest first, fetches named after their function when there are symbols
+    pub fn dump(&self, out: &mut impl Write) -> io::Result<()>
+    {
+        let entries = self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for &(cycle, fetch, transfer_type, address, data) in entries.iter()
+        {
+            let (access, width) = if fetch { ("fetch", "word") } else { transfer_type.describe() };
+            match self.symbols.location(address).filter(|_| fetch)
+            {
+                Some(location) =>
+                    writeln!(out, "{cycle:>12} {access:<5} {width:<4} {address:#010x} {data:#010x} <{location}>")?,
+                None => writeln!(out, "{cycle:>12} {access:<5} {width:<4} {address:#010x} {data:#010x}")?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use computer_config::Config;
+
+use crate::memory::Memory;
+use crate::symbols::Symbols;
+
+// conditional branches: beq, bne, blez, bgtz
+fn is_branch(instruction: u32) -> bool
+{
+    (4..=7).contains(&(instruction >> 26))
+}
+
+// lines of one source file: line -> hits, per branch instruction on it (executions, taken, not taken)
+type Lines = BTreeMap<u32, (u64, Vec<(u64, u64, u64)>)>;
+
+// line, name, hits
+type Functions = Vec<(u32, String, u64)>;
+
+// executed instructions and branch directions, written as an lcov tracefile
+pub(crate) struct Coverage
+{
+    out: Option<File>, // none once written
+    symbols: Symbols,
+    images: Vec<(String, u32, Vec<u8>)>, // name, address, contents as loaded: the ROM and the program region
+    executed: HashMap<u32, u64>, // pc -> count
+    branches: HashMap<u32, (u64, u64)>, // pc -> taken, not taken
+    pending_branch: Option<u32>, // its direction shows in where the next instruction is
+}
+
+impl Coverage
+{
+    // the images are copied so the report can be written from a panic hook, without the machine
+    pub(crate) fn new(config: &Config, symbols: &Symbols, memory: &Memory) -> Option<Coverage>
+    {
+        let path = config.coverage().as_ref()?;
+        let program = memory.program_address();
+        Some(Coverage
+        {
+            out: Some(File::create(path).expect("Bad coverage file")),
+            symbols: symbols.clone(),
+            images: vec![
+                (config.rom_filename().clone().unwrap_or_else(|| "rom".to_string()), 0,
+                 memory.bytes(0, memory.rom_size()).to_vec()),
+                (config.program_filename().clone().unwrap_or_else(|| "program".to_string()), program,
+                 memory.bytes(program, memory.size() - program).to_vec()),
+            ],
+            executed: HashMap::new(),
+            branches: HashMap::new(),
+            pending_branch: None,
+        })
+    }
+
+    // interrupted holds where the interrupted code continues when pc is the first instruction of the handler
+    pub(crate) fn retire(&mut self, (pc, instruction): (u32, u32), interrupted: Option<u32>)
+    {
+        if let Some(branch) = self.pending_branch.take()
+        {
+            // a branch to the next instruction counts as not taken, both go the same way
+            let (taken, not_taken) = self.branches.entry(branch).or_default();
+            if interrupted.unwrap_or(pc) == branch.wrapping_add(4)
+            {
+                *not_taken += 1;
+            }
+            else
+            {
+                *taken += 1;
+            }
+        }
+
+        *self.executed.entry(pc).or_default() += 1;
+        if is_branch(instruction)
+        {
+            self.pending_branch = Some(pc);
+        }
+    }
+
+    fn read_word(&self, address: u32) -> Option<u32>
+    {
+        self.images.iter().find_map(|(_, start, bytes)|
+            {
+                let offset = address.checked_sub(*start)? as usize;
+                let word = bytes.get(offset..offset.checked_add(4)?)?;
+                Some(u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+            })
+    }
+
+    fn hits(&self, address: u32) -> u64
+    {
+        self.executed.get(&address).copied().unwrap_or(0)
+    }
+
+    // adds the instruction at the address to the line, the line counts as often as its busiest instruction ran
+    fn add_instruction(&self, lines: &mut Lines, line: u32, address: u32, instruction: Option<u32>)
+    {
+        let (hits, branches) = lines.entry(line).or_default();
+        *hits = (*hits).max(self.hits(address));
+        if instruction.is_some_and(is_branch)
+        {
+            let (taken, not_taken) = self.branches.get(&address).copied().unwrap_or_default();
+            branches.push((self.hits(address), taken, not_taken));
+        }
+    }
+
+    // with a line table files and lines are the source's, otherwise each image is a file and its instructions are
+    // lines, numbered from 1 and spanning the lowest to the highest executed address
+    fn collect(&self) -> BTreeMap<String, (Lines, Functions)>
+    {
+        let mut files: BTreeMap<String, (Lines, Functions)> = BTreeMap::new();
+        if self.symbols.has_lines()
+        {
+            for (start, end, file, line) in self.symbols.line_ranges()
+            {
+                let (lines, _) = files.entry(file.to_string()).or_default();
+                for address in (start..end).step_by(4)
+                {
+                    self.add_instruction(lines, line, address, self.read_word(address));
+                }
+            }
+            for (address, name) in self.symbols.entries()
+            {
+                if let Some((file, line)) = self.symbols.line(*address)
+                {
+                    let (_, functions) = files.entry(file.to_string()).or_default();
+                    functions.push((line, name.clone(), self.hits(*address)));
+                }
+            }
+            return files;
+        }
+
+        for (name, start, bytes) in &self.images
+        {
+            let (start, end) = (*start as u64, *start as u64 + bytes.len() as u64);
+            let executed = self.executed.keys().filter(|&&pc| pc as u64 >= start && (pc as u64) < end);
+            let (first, last) = match (executed.clone().min(), executed.max())
+            {
+                (Some(&first), Some(&last)) => (first, last),
+                _ => continue,
+            };
+            let line = |address: u32| (address - first) / 4 + 1;
+            let (lines, functions) = files.entry(name.to_string()).or_default();
+            for address in (first..=last).step_by(4)
+            {
+                self.add_instruction(lines, line(address), address, self.read_word(address));
+            }
+            for (address, symbol) in self.symbols.entries()
+            {
+                if *address >= first && *address <= last && (address - first) % 4 == 0
+                {
+                    functions.push((line(*address), symbol.clone(), self.hits(*address)));
+                }
+            }
+        }
+        files
+    }
+
+    fn write_lcov(out: &mut impl Write, files: &BTreeMap<String, (Lines, Functions)>) -> io::Result<()>
+    {
+        writeln!(out, "TN:")?;
+        for (file, (lines, functions)) in files
+        {
+            writeln!(out, "SF:{file}")?;
+            for (line, name, _) in functions
+            {
+                writeln!(out, "FN:{line},{name}")?;
+            }
+            for (_, name, hits) in functions
+            {
+                writeln!(out, "FNDA:{hits},{name}")?;
+            }
+            writeln!(out, "FNF:{}", functions.len())?;
+            writeln!(out, "FNH:{}", functions.iter().filter(|(_, _, hits)| *hits > 0).count())?;
+
+            let (mut found, mut hit) = (0, 0);
+            for (line, (_, branches)) in lines
+            {
+                for (block, &(executions, taken, not_taken)) in branches.iter().enumerate()
+                {
+                    for (branch, count) in [taken, not_taken].into_iter().enumerate()
+                    {
+                        // a branch that never ran has no directions yet
+                        match executions
+                        {
+                            0 => writeln!(out, "BRDA:{line},{block},{branch},-")?,
+                            _ => writeln!(out, "BRDA:{line},{block},{branch},{count}")?,
+                        }
+                        found += 1;
+                        hit += (count > 0) as u32;
+                    }
+                }
+            }
+            writeln!(out, "BRF:{found}")?;
+            writeln!(out, "BRH:{hit}")?;
+
+            for (line, (hits, _)) in lines
+            {
+                writeln!(out, "DA:{line},{hits}")?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines.values().filter(|(hits, _)| *hits > 0).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    // once, later calls find nothing to write
+    pub(crate) fn write(&mut self)
+    {
+        let file = match self.out.take()
+        {
+            Some(file) => file,
+            None => return,
+        };
+        let files = self.collect();
+        let mut out = BufWriter::new(file);
+        if let Err(error) = Self::write_lcov(&mut out, &files).and_then(|_| out.flush())
+        {
+            eprintln!("Cannot write coverage: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // counts down from 2, then branches over an unreachable branch and an uncalled function
+    const PROGRAM: [u32; 7] = [
+        0x2408_0002, // main: addiu $t0, $zero, 2
+        0x2508_ffff, // loop: addiu $t0, $t0, -1
+        0x1500_fff8, // bne $t0, $zero, loop
+        0x1000_0008, // beq $zero, $zero, end
+        0x1500_0004, // bne $t0, $zero, end
+        0x0000_0000, // f: nop
+        0x0000_0000, // end: nop
+    ];
+
+    fn run(symbols: Symbols) -> String
+    {
+        let program: Vec<u8> = PROGRAM.iter().flat_map(|word| word.to_be_bytes()).collect();
+        let mut coverage = Coverage
+        {
+            out: None,
+            symbols,
+            images: vec![("rom".to_string(), 0, vec![0; 16]), ("program.bin".to_string(), 0x100, program)],
+            executed: HashMap::new(),
+            branches: HashMap::new(),
+            pending_branch: None,
+        };
+        for pc in [0x100, 0x104, 0x108, 0x104, 0x108, 0x10c, 0x118]
+        {
+            coverage.retire((pc, PROGRAM[(pc as usize - 0x100) / 4]), None);
+        }
+
+        let mut out = Vec::new();
+        Coverage::write_lcov(&mut out, &coverage.collect()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn without_a_line_table_instructions_are_lines()
+    {
+        let symbols = Symbols::from_map("100 main\n104 loop\n114 f\n118 end\n");
+        let expected = "TN:\nSF:program.bin\n\
+                        FN:1,main\nFN:2,loop\nFN:6,f\nFN:7,end\n\
+                        FNDA:1,main\nFNDA:2,loop\nFNDA:0,f\nFNDA:1,end\nFNF:4\nFNH:3\n\
+                        BRDA:3,0,0,1\nBRDA:3,0,1,1\nBRDA:4,0,0,1\nBRDA:4,0,1,0\nBRDA:5,0,0,-\nBRDA:5,0,1,-\n\
+                        BRF:6\nBRH:3\n\
+                        DA:1,1\nDA:2,2\nDA:3,2\nDA:4,1\nDA:5,0\nDA:6,0\nDA:7,1\nLF:7\nLH:5\nend_of_record\n";
+        assert_eq!(run(symbols), expected);
+    }
+
+    #[test]
+    fn with_a_line_table_lines_are_the_source_lines()
+    {
+        let rows = vec![(0x100, 10, "a.s".into()), (0x108, 11, "a.s".into()), (0x110, 12, "b.s".into()),
+                        (0x118, 0, "b.s".into())];
+        let symbols = Symbols::from_map("100 main\n118 end\n").with_lines(rows);
+        let expected = "TN:\nSF:a.s\nFN:10,main\nFNDA:1,main\nFNF:1\nFNH:1\n\
+                        BRDA:11,0,0,1\nBRDA:11,0,1,1\nBRDA:11,1,0,1\nBRDA:11,1,1,0\nBRF:4\nBRH:3\n\
+                        DA:10,2\nDA:11,2\nLF:2\nLH:2\nend_of_record\n\
+                        SF:b.s\nFNF:0\nFNH:0\nBRDA:12,0,0,-\nBRDA:12,0,1,-\nBRF:2\nBRH:0\n\
+                        DA:12,0\nLF:1\nLH:0\nend_of_record\n";
+        assert_eq!(run(symbols), expected);
+    }
+}
@@ -2,6 +2,8 @@ use crate::cpu_aux::TransferType;
 use crate::cpu_aux::TransferType::*;
 use crate::cpu_aux::Phase;
 use crate::cpu_aux::Phase::*;
+use crate::cpu_aux::*;
+use crate::symbols::Symbols;
 
 pub(crate) struct CPU
 {
@@ -18,11 +20,25 @@ pub(crate) struct CPU
     // hi - 33
     // lo - 34
 
+    // coprocessor 0
+    status: u32,
+    cause: u32,
+    epc: u32,
+    ebase: u32,
+
     instruction: u32,
     result: i32,
     target: u8, // 5 bits
     in_out: (TransferType, u32, u32), // transfer type, address, data
     phase: Phase,
+
+    // register writes of the current instruction, for the instruction trace
+    record_writes: bool,
+    reg_writes: Vec<(u8, i32)>,
+
+    interrupt_return: Option<u32>, // set when the last fetch entered the interrupt handler
+
+    symbols: Symbols, // for naming addresses in crash messages
 }
 
 impl CPU
@@ -36,11 +52,19 @@ impl CPU
             hi: 0,
             lo: 0,
             pc: 0,
+            status: 0,
+            cause: 0,
+            epc: 0,
+            ebase: 0,
             instruction: 0,
             result: 0,
             target: 0,
             in_out: (NoTransfer, 0, 0),
-            phase: IF
+            phase: IF,
+            record_writes: false,
+            reg_writes: Vec::new(),
+            interrupt_return: None,
+            symbols: Symbols::default(),
         }
     }
     fn next_phase(&mut self)
@@ -56,6 +80,11 @@ impl CPU
 
     fn write_to_reg(&mut self, num: u8, data: i32)
     {
+        if self.record_writes && num != 0 && num != 32
+        {
+            self.reg_writes.push((num, data)); // pc changes show in the next instruction
+        }
+
         match num
         {
             0 => return, // 0 registers is constant 0
@@ -87,8 +116,40 @@ impl CPU
         return self.in_out;
     }
 
+    pub(crate) fn set_interrupt(&mut self, interrupt: Interrupt, active: bool)
+    {
+        let bit = 1 << (10 + interrupt as u32);
+        if active
+        {
+            self.cause |= bit;
+        }
+        else
+        {
+            self.cause &= !bit;
+        }
+    }
+
+    fn interrupt_pending(&self) -> bool
+    {
+        let enabled = self.status & STATUS_IE != 0 && self.status & STATUS_EXL == 0;
+        enabled && (self.cause & self.status & INTERRUPT_MASK) != 0
+    }
+
     fn fetch(&mut self)
     {
+        self.reg_writes.clear();
+        self.interrupt_return = None;
+
+        if self.interrupt_pending()
+        {
+            self.interrupt_return = Some(self.pc);
+            // enter the exception handler instead of fetching the next instruction
+            self.epc = self.pc;
+            self.status |= STATUS_EXL;
+            self.cause &= !(0x1F << 2); // ExcCode 0 - interrupt
+            self.pc = self.ebase + EXCEPTION_VECTOR_OFFSET;
+        }
+
         let (transfer_type, address, data) = (ReadWord, self.pc, 0);
         self.in_out = (transfer_type, address, data);
     }
@@ -97,6 +158,7 @@ impl CPU
     {
         self.instruction = self.in_out.2;
         self.pc += 4;
+        self.in_out.0 = NoTransfer; // loads and stores put their own transfer on the bus
 
         let opcode = (self.instruction & 0b_111111_00000_00000_00000_00000_000000) >> 26;
 
@@ -111,7 +173,7 @@ impl CPU
         let imm = (self.instruction & 0xFFFF) as u16 as i16;
 
         // J
-        let address = self.instruction & 0x_00_03_FF_FF; // 26 youngest bits
+        let address = self.instruction & 0x_03_FF_FF_FF; // 26 youngest bits
 
         //#[cfg(debug_assertions)]
         //println!("Instruction: {:#032b}", self.instruction);
@@ -150,7 +212,7 @@ impl CPU
                 39 => self.nor(rd, rs, rt),
                 42 => self.slt(rd, rs, rt),
                 43 => self.sltu(rd, rs, rt),
-                _ => panic!("Bad instruction at {}", self.pc - 4),
+                _ => panic!("Bad instruction at {}", self.symbols.describe(self.pc - 4)),
             }
         }
         else
@@ -171,6 +233,7 @@ impl CPU
                 13 => self.ori(rt, rs, imm),
                 14 => self.xori(rt, rs, imm),
                 15 => self.lui(rt, imm),
+                16 => self.cop0(rs, rt, rd, funct),
                 32 => self.lb(rt, rs, imm),
                 33 => self.lh(rt, rs, imm),
                 34 => self.lw(rt, rs, imm),
@@ -179,7 +242,7 @@ impl CPU
                 40 => self.sb(rt, rs, imm),
                 41 => self.sh(rt, rs, imm),
                 43 => self.sw(rt, rs, imm),
-                _ => panic!("Bad instruction at {}", self.pc - 4),
+                _ => panic!("Bad instruction at {}", self.symbols.describe(self.pc - 4)),
             }
         }
     }
@@ -363,7 +426,7 @@ impl CPU // opcodes
 
     fn addu(&mut self, rd: u8, rs: u8, rt: u8)
     {
-        let res = self.reg[rs as usize] as u32 + self.reg[rt as usize] as u32;
+        let res = (self.reg[rs as usize] as u32).wrapping_add(self.reg[rt as usize] as u32);
         self.write_to_reg(rd, res as i32);
     }
 
@@ -375,7 +438,7 @@ impl CPU // opcodes
 
     fn subu(&mut self, rd: u8, rs: u8, rt: u8)
     {
-        let res = self.reg[rs as usize] as u32 - self.reg[rt as usize] as u32;
+        let res = (self.reg[rs as usize] as u32).wrapping_sub(self.reg[rt as usize] as u32);
         self.write_to_reg(rd, res as i32);
     }
 
@@ -481,7 +544,7 @@ impl CPU // opcodes
 
     fn addiu(&mut self, rt: u8, rs: u8, imm: i16)
     {
-        let res = (self.reg[rs as usize] as u32) + (imm as i32 as u32);
+        let res = (self.reg[rs as usize] as u32).wrapping_add(imm as i32 as u32);
         self.write_to_reg(rt, res as i32);
     }
 
@@ -595,10 +658,110 @@ impl CPU // opcodes
     }
 }
 
+impl CPU // coprocessor 0
+{
+    fn cop0(&mut self, rs: u8, rt: u8, rd: u8, funct: u8)
+    {
+        let select = funct & 0b111;
+        match rs
+        {
+            0 => self.mfc0(rt, rd, select),
+            4 => self.mtc0(rt, rd, select),
+            16 if funct == 0x18 => self.eret(),
+            _ => panic!("Bad instruction at {}", self.symbols.describe(self.pc - 4)),
+        }
+    }
+
+    fn cp0_register(&self, register: (u8, u8)) -> u32
+    {
+        match register
+        {
+            CP0_STATUS => self.status,
+            CP0_CAUSE => self.cause,
+            CP0_EPC => self.epc,
+            CP0_EBASE => self.ebase,
+            _ => 0,
+        }
+    }
+
+    fn mfc0(&mut self, rt: u8, rd: u8, select: u8)
+    {
+        let value = self.cp0_register((rd, select));
+        self.write_to_reg(rt, value as i32);
+    }
+
+    fn mtc0(&mut self, rt: u8, rd: u8, select: u8)
+    {
+        let value = self.reg[rt as usize] as u32;
+        match (rd, select)
+        {
+            CP0_STATUS => self.status = value,
+            // pending hardware interrupts are wired to devices
+            CP0_CAUSE => self.cause = (self.cause & HARDWARE_INTERRUPTS) | (value & !HARDWARE_INTERRUPTS),
+            CP0_EPC => self.epc = value,
+            CP0_EBASE => self.ebase = value & !0xFFF,
+            _ => {},
+        }
+    }
+
+    fn eret(&mut self)
+    {
+        self.status &= !STATUS_EXL;
+        self.write_to_reg(32, self.epc as i32);
+    }
+}
+
 impl CPU // dump
 {
-    #[allow(unused)]
-    fn registers(&self) -> ([i32; 35], [f32; 32])
+    pub(crate) fn pc(&self) -> u32
+    {
+        self.pc
+    }
+
+    // same numbering as registers(), pc has to stay aligned
+    pub(crate) fn set_register(&mut self, num: u8, data: i32)
+    {
+        self.write_to_reg(num, data);
+    }
+
+    pub(crate) fn set_symbols(&mut self, symbols: Symbols)
+    {
+        self.symbols = symbols;
+    }
+
+    pub(crate) fn set_record_writes(&mut self, record: bool)
+    {
+        self.record_writes = record;
+    }
+
+    // registers written by the last instruction, same numbering as registers()
+    pub(crate) fn reg_writes(&self) -> &[(u8, i32)]
+    {
+        &self.reg_writes
+    }
+
+    // where the interrupted code continues, when the last fetch entered the interrupt handler
+    pub(crate) fn interrupt_return(&self) -> Option<u32>
+    {
+        self.interrupt_return
+    }
+
+    pub(crate) fn status(&self) -> u32
+    {
+        self.status
+    }
+
+    pub(crate) fn cause(&self) -> u32
+    {
+        self.cause
+    }
+
+    pub(crate) fn set_status(&mut self, status: u32)
+    {
+        self.status = status;
+    }
+
+    pub(crate) fn registers(&self) -> ([i32; 35], [f32; 32])
     {
         let mut int_registers = [0; 35];
         for i in 0..32
@@ -618,3 +781,113 @@ impl CPU // dump
         return (int_registers, float_registers);
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn unsigned_arithmetic_wraps()
+    {
+        let mut cpu = CPU::new();
+        cpu.reg[8] = 0;
+        cpu.addiu(9, 8, -1);
+        assert_eq!(cpu.reg[9], -1);
+        cpu.addiu(10, 9, 1);
+        assert_eq!(cpu.reg[10], 0);
+
+        cpu.reg[11] = i32::MAX;
+        cpu.reg[12] = 1;
+        cpu.addu(13, 11, 12);
+        assert_eq!(cpu.reg[13], i32::MIN);
+        cpu.subu(14, 8, 12);
+        assert_eq!(cpu.reg[14], -1);
+    }
+
+    // runs one instruction fetched from the pc, the bus answering with it
+    fn execute(cpu: &mut CPU, instruction: u32)
+    {
+        for data in [0, instruction, 0, 0]
+        {
+            cpu.tick(data);
+        }
+    }
+
+    #[test]
+    fn jump_target_uses_all_26_bits()
+    {
+        // a target field with bit 20 set: masked to 18 bits it lost that bit and jumped to 0
+        let mut cpu = CPU::new();
+        execute(&mut cpu, (2 << 26) | 0x0010_0000);
+        assert_eq!(cpu.pc, 0x0040_0000);
+
+        // the highest target, jal keeps the return address
+        let mut cpu = CPU::new();
+        execute(&mut cpu, (3 << 26) | 0x03FF_FFFF);
+        assert_eq!(cpu.pc, 0x0FFF_FFFC);
+        assert_eq!(cpu.reg[31], 4);
+    }
+
+    #[test]
+    fn fetch_does_not_stay_on_the_bus()
+    {
+        // the fetch was a word read, left there the bus read the instruction again during addu
+        let mut cpu = CPU::new();
+        cpu.tick(0);
+        let (transfer_type, _, _) = cpu.tick((8 << 21) | (9 << 16) | (10 << 11) | 0x21); // addu $t2, $t0, $t1
+        assert!(matches!(transfer_type, NoTransfer));
+
+        // after a load the next instruction puts nothing on the bus either
+        let mut cpu = CPU::new();
+        cpu.reg[8] = 0x100;
+        cpu.tick(0);
+        let (transfer_type, address, _) = cpu.tick((34 << 26) | (8 << 21) | (9 << 16) | 4); // lw $t1, 4($t0)
+        assert!(matches!(transfer_type, ReadWord));
+        assert_eq!(address, 0x104);
+        cpu.tick(7);
+        cpu.tick(0);
+        assert_eq!(cpu.reg[9], 7);
+        cpu.tick(0);
+        let (transfer_type, _, _) = cpu.tick(0); // nop
+        assert!(matches!(transfer_type, NoTransfer));
+    }
+
+    #[test]
+    fn interrupt_enters_the_handler_and_eret_returns()
+    {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x40;
+        cpu.ebase = 0x1000;
+        cpu.status = STATUS_IE | (1 << 10); // vblank enabled
+        cpu.set_interrupt(Interrupt::VBlank, true);
+
+        let (_, address, _) = cpu.tick(0);
+        assert_eq!(address, 0x1000 + EXCEPTION_VECTOR_OFFSET);
+        assert_eq!(cpu.interrupt_return(), Some(0x40));
+        assert_eq!((cpu.epc, cpu.status & STATUS_EXL), (0x40, STATUS_EXL));
+        assert_eq!(cpu.cause & (0x1F << 2), 0);
+        for data in [0, 0, 0] // nop
+        {
+            cpu.tick(data);
+        }
+
+        // still pending but masked by EXL until the handler returns
+        let (_, address, _) = cpu.tick(0);
+        assert_eq!(address, 0x1000 + EXCEPTION_VECTOR_OFFSET + 4);
+        assert_eq!(cpu.interrupt_return(), None);
+        for data in [(16 << 26) | (16 << 21) | 0x18, 0, 0] // eret
+        {
+            cpu.tick(data);
+        }
+        assert_eq!((cpu.pc, cpu.status & STATUS_EXL), (0x40, 0));
+
+        // the interrupt lines are wired to the devices, mtc0 cannot raise them
+        cpu.set_interrupt(Interrupt::VBlank, false);
+        cpu.reg[8] = -1;
+        execute(&mut cpu, (16 << 26) | (4 << 21) | (8 << 16) | (13 << 11)); // mtc0 $t0, Cause
+        assert_eq!(cpu.cause & HARDWARE_INTERRUPTS, 0);
+        let (_, address, _) = cpu.tick(0);
+        assert_eq!(address, 0x44);
+    }
+}
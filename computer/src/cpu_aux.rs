@@ -18,4 +18,44 @@ pub(crate) enum TransferType
     WriteByte = 6,
     WriteHalf = 7,
     WriteWord = 8,
-}
\ No newline at end of file
+}
+
+impl TransferType
+{
+    // access and width, for traces
+    pub(crate) fn describe(&self) -> (&'static str, &'static str)
+    {
+        use TransferType::*;
+        match self
+        {
+            NoTransfer => ("none", ""),
+            ReadByte | ReadByteUnsigned => ("read", "byte"),
+            ReadHalf | ReadHalfUnsigned => ("read", "half"),
+            ReadWord => ("read", "word"),
+            WriteByte => ("write", "byte"),
+            WriteHalf => ("write", "half"),
+            WriteWord => ("write", "word"),
+        }
+    }
+}
+
+// hardware interrupt lines, wired to Cause.IP2 upwards
+#[derive(Clone, Copy)]
+pub(crate) enum Interrupt
+{
+    VBlank = 0,
+    Blitter = 1,
+    Audio = 2,
+}
+
+// coprocessor 0 registers (number, select)
+pub(crate) const CP0_STATUS: (u8, u8) = (12, 0);
+pub(crate) const CP0_CAUSE: (u8, u8) = (13, 0);
+pub(crate) const CP0_EPC: (u8, u8) = (14, 0);
+pub(crate) const CP0_EBASE: (u8, u8) = (15, 1);
+
+pub(crate) const STATUS_IE: u32 = 1 << 0;
+pub(crate) const STATUS_EXL: u32 = 1 << 1;
+pub(crate) const INTERRUPT_MASK: u32 = 0xFF << 8; // IM in Status, IP in Cause
+pub(crate) const HARDWARE_INTERRUPTS: u32 = 0x3F << 10;
+pub(crate) const EXCEPTION_VECTOR_OFFSET: u32 = 0x180;
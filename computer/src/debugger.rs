@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use computer_config::{WatchAction, WatchKind};
+
+use crate::Computer;
+use crate::bus_trace::BusTrace;
+use crate::cpu_aux::TransferType;
+use crate::symbols::Symbols;
+
+// the instruction being executed, readable from a panic hook when the guest brings the emulator down
+#[derive(Clone)]
+pub struct ExecutingPc(Arc<AtomicU32>);
+
+impl ExecutingPc
+{
+    pub fn get(&self) -> u32
+    {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Computer // debugging
+{
+    // next instruction to execute
+    pub fn pc(&self) -> u32
+    {
+        self.cpu.pc()
+    }
+
+    // 0-31 general purpose, 32 pc, 33 hi, 34 lo; floating point
+    pub fn registers(&self) -> ([i32; 35], [f32; 32])
+    {
+        self.cpu.registers()
+    }
+
+    // numbering as in registers(), false for a misaligned pc
+    pub fn set_register(&mut self, index: usize, value: i32) -> bool
+    {
+        if index > 34 || (index == 32 && value % 4 != 0)
+        {
+            return false;
+        }
+        self.cpu.set_register(index as u8, value);
+        true
+    }
+
+    // coprocessor 0 status and cause
+    pub fn status(&self) -> u32
+    {
+        self.cpu.status()
+    }
+
+    pub fn cause(&self) -> u32
+    {
+        self.cpu.cause()
+    }
+
+    pub fn set_status(&mut self, status: u32)
+    {
+        self.cpu.set_status(status);
+    }
+
+    pub fn memory_size(&self) -> u32
+    {
+        self.memory.size()
+    }
+
+    pub fn read_memory(&self, address: u32, len: u32) -> Option<&[u8]>
+    {
+        if address as u64 + len as u64 > self.memory.size() as u64
+        {
+            return None;
+        }
+        Some(self.memory.bytes(address, len))
+    }
+
+    // unlike guest stores this may patch ROM, returns false if out of range
+    pub fn write_memory(&mut self, address: u32, data: &[u8]) -> bool
+    {
+        if address as u64 + data.len() as u64 > self.memory.size() as u64
+        {
+            return false;
+        }
+        self.memory.patch(address, data);
+        true
+    }
+
+    pub fn add_breakpoint(&mut self, address: u32)
+    {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool
+    {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> Vec<u32>
+    {
+        let mut breakpoints: Vec<u32> = self.breakpoints.iter().copied().collect();
+        breakpoints.sort();
+        breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, address: u32, len: u32, kind: WatchKind, action: WatchAction)
+    {
+        self.watchpoints.push((address, len.max(1), kind, action));
+    }
+
+    // whatever the action
+    pub fn remove_watchpoint(&mut self, address: u32, len: u32, kind: WatchKind) -> bool
+    {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&(watch_address, watch_len, watch_kind, _)|
+            (watch_address, watch_len, watch_kind) != (address, len.max(1), kind));
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[(u32, u32, WatchKind, WatchAction)]
+    {
+        &self.watchpoints
+    }
+
+    // handle on the recent bus transfers, if tracing is on
+    pub fn bus_trace(&self) -> Option<BusTrace>
+    {
+        self.trace.clone()
+    }
+
+    pub fn symbols(&self) -> Symbols
+    {
+        self.symbols.clone()
+    }
+
+    pub fn executing_pc(&self) -> ExecutingPc
+    {
+        ExecutingPc(self.executing.clone())
+    }
+
+    // kind and address of the access that halted the computer
+    pub fn take_watch_hit(&mut self) -> Option<(WatchKind, u32)>
+    {
+        self.watch_hit.take()
+    }
+
+    // a halted computer ignores cycle() until resumed
+    pub fn halted(&self) -> bool
+    {
+        self.halted
+    }
+
+    pub fn halt(&mut self)
+    {
+        self.halted = true;
+    }
+
+    pub fn resume(&mut self)
+    {
+        self.watch_hit = None;
+        self.halted = false;
+    }
+
+    // executes one instruction and stays halted
+    pub fn step(&mut self)
+    {
+        self.watch_hit = None;
+        self.halted = false;
+        self.cycle();
+        self.halted = true;
+    }
+
+    pub(crate) fn check_breakpoint(&mut self)
+    {
+        if self.watch_hit.is_some() || (!self.breakpoints.is_empty() && self.breakpoints.contains(&self.cpu.pc()))
+        {
+            self.halted = true;
+            return;
+        }
+
+        // execute watchpoints stop before the instruction, like breakpoints
+        let pc = self.cpu.pc();
+        let executing = self.watchpoints.iter().any(|&(address, len, kind, action)|
+            kind == WatchKind::Execute && action == WatchAction::Halt && pc >= address && pc - address < len);
+        if executing
+        {
+            self.watch_hit = Some((WatchKind::Execute, pc));
+            self.halted = true;
+        }
+    }
+
+    // looks at the transfer currently on the bus, fetch is the instruction read
+    pub(crate) fn observe_bus(&mut self, fetch: bool)
+    {
+        use TransferType::*;
+        let (write, len) = match self.tt_bus
+        {
+            NoTransfer => return,
+            ReadByte | ReadByteUnsigned => (false, 1),
+            ReadHalf | ReadHalfUnsigned => (false, 2),
+            ReadWord => (false, 4),
+            WriteByte => (true, 1),
+            WriteHalf => (true, 2),
+            WriteWord => (true, 4),
+        };
+        let (address, data) = (self.addres_bus, self.data_bus);
+
+        if let Some(trace) = &self.trace
+        {
+            trace.record(self.cycles, fetch, self.tt_bus, address, data);
+        }
+
+        // loads and stores are on the bus after pc moved past them
+        let pc = if fetch { address } else { self.cpu.pc().wrapping_sub(4) };
+
+        for &(watch_address, watch_len, kind, action) in &self.watchpoints
+        {
+            let overlaps = address < watch_address.saturating_add(watch_len) && watch_address < address + len;
+            let matches = match kind
+            {
+                WatchKind::Write => !fetch && write,
+                WatchKind::Read => !fetch && !write,
+                WatchKind::Access => !fetch,
+                WatchKind::Execute => fetch && action == WatchAction::Log, // halting is done before the fetch
+            };
+            if !overlaps || !matches
+            {
+                continue;
+            }
+
+            match action
+            {
+                WatchAction::Log =>
+                {
+                    let (access, width) = if fetch { ("fetch", "word") } else { self.tt_bus.describe() };
+                    let pc = self.symbols.describe(pc);
+                    eprintln!("watch: pc {pc} {access} {width} {address:#010x} = {data:#010x}");
+                },
+                WatchAction::Halt =>
+                {
+                    self.watch_hit = Some((kind, address));
+                    return;
+                },
+            }
+        }
+    }
+}
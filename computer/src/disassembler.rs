@@ -0,0 +1,258 @@
+use std::fmt;
+
+use crate::symbols::Symbols;
+
+pub const REGISTER_NAMES: [&str; 32] = [
+    "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3",
+    "$t0", "$t1", "$t2", "$t3", "$t4", "$t5", "$t6", "$t7",
+    "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7",
+    "$t8", "$t9", "$k0", "$k1", "$gp", "$sp", "$fp", "$ra",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand
+{
+    Register(u8),
+    Cp0Register(u8),
+    Immediate(i32), // signed immediates, shift amounts, cop0 selects
+    UnsignedImmediate(u16), // logical immediates and lui
+    Target(u32), // absolute branch or jump destination
+    Memory { base: u8, offset: i16 },
+}
+
+// an instruction word split into the fields the CPU decodes, plus its assembly form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction
+{
+    pub word: u32,
+    pub opcode: u8,
+    pub rs: u8,
+    pub rt: u8,
+    pub rd: u8,
+    pub shift: u8,
+    pub funct: u8,
+    pub imm: i16,
+    pub address: u32, // J-type, 26 bits
+    pub mnemonic: Option<&'static str>, // none if the CPU does not execute it
+    pub operands: Vec<Operand>,
+}
+
+impl fmt::Display for Operand
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Operand::Register(register) => write!(f, "{}", REGISTER_NAMES[register as usize]),
+            Operand::Cp0Register(register) => write!(f, "${register}"),
+            Operand::Immediate(value) => write!(f, "{value}"),
+            Operand::UnsignedImmediate(value) => write!(f, "{value:#x}"),
+            Operand::Target(address) => write!(f, "{address:#x}"),
+            Operand::Memory { base, offset } => write!(f, "{offset}({})", REGISTER_NAMES[base as usize]),
+        }
+    }
+}
+
+impl fmt::Display for DecodedInstruction
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let mnemonic = match self.mnemonic
+        {
+            Some(mnemonic) => mnemonic,
+            None => return write!(f, ".word {:#010x}", self.word),
+        };
+
+        write!(f, "{mnemonic}")?;
+        for (i, operand) in self.operands.iter().enumerate()
+        {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{operand}")?;
+        }
+        Ok(())
+    }
+}
+
+// address is where the instruction lies, for branch and jump targets
+pub fn decode(word: u32, address: u32) -> DecodedInstruction
+{
+    use Operand::*;
+
+    let opcode = (word >> 26) as u8;
+    let rs = ((word >> 21) & 0x1F) as u8;
+    let rt = ((word >> 16) & 0x1F) as u8;
+    let rd = ((word >> 11) & 0x1F) as u8;
+    let shift = ((word >> 6) & 0x1F) as u8;
+    let funct = (word & 0x3F) as u8;
+    let imm = (word & 0xFFFF) as u16 as i16;
+    let jump_address = word & 0x03FF_FFFF;
+
+    // branches are relative to the next instruction, in bytes
+    let branch_target = Target(address.wrapping_add(4).wrapping_add(imm as i32 as u32));
+    let jump_target = Target((jump_address << 2) | (address.wrapping_add(4) & 0xF000_0000));
+    let memory = Memory { base: rs, offset: imm };
+
+    let (mnemonic, operands) = if opcode == 0
+    {
+        match funct
+        {
+            0 if word == 0 => ("nop", vec![]),
+            0 => ("sll", vec![Register(rd), Register(rt), Immediate(shift as i32)]),
+            2 => ("srl", vec![Register(rd), Register(rt), Immediate(shift as i32)]),
+            3 => ("sra", vec![Register(rd), Register(rt), Immediate(shift as i32)]),
+            4 => ("sllv", vec![Register(rd), Register(rt), Register(rs)]),
+            6 => ("srlv", vec![Register(rd), Register(rt), Register(rs)]),
+            7 => ("srav", vec![Register(rd), Register(rt), Register(rs)]),
+            8 => ("jr", vec![Register(rs)]),
+            9 => ("jalr", vec![Register(rd), Register(rs)]),
+            12 => ("syscall", vec![]),
+            16 => ("mfhi", vec![Register(rd)]),
+            17 => ("mthi", vec![Register(rs)]),
+            18 => ("mflo", vec![Register(rd)]),
+            19 => ("mtlo", vec![Register(rs)]),
+            24 => ("mult", vec![Register(rs), Register(rt)]),
+            25 => ("multu", vec![Register(rs), Register(rt)]),
+            26 => ("div", vec![Register(rs), Register(rt)]),
+            27 => ("divu", vec![Register(rs), Register(rt)]),
+            32 => ("add", vec![Register(rd), Register(rs), Register(rt)]),
+            33 => ("addu", vec![Register(rd), Register(rs), Register(rt)]),
+            34 => ("sub", vec![Register(rd), Register(rs), Register(rt)]),
+            35 => ("subu", vec![Register(rd), Register(rs), Register(rt)]),
+            36 => ("and", vec![Register(rd), Register(rs), Register(rt)]),
+            37 => ("or", vec![Register(rd), Register(rs), Register(rt)]),
+            38 => ("xor", vec![Register(rd), Register(rs), Register(rt)]),
+            39 => ("nor", vec![Register(rd), Register(rs), Register(rt)]),
+            42 => ("slt", vec![Register(rd), Register(rs), Register(rt)]),
+            43 => ("sltu", vec![Register(rd), Register(rs), Register(rt)]),
+            _ => ("", vec![]),
+        }
+    }
+    else
+    {
+        match opcode
+        {
+            2 => ("j", vec![jump_target]),
+            3 => ("jal", vec![jump_target]),
+            4 => ("beq", vec![Register(rs), Register(rt), branch_target]),
+            5 => ("bne", vec![Register(rs), Register(rt), branch_target]),
+            6 => ("blez", vec![Register(rs), branch_target]),
+            7 => ("bgtz", vec![Register(rs), branch_target]),
+            8 => ("addi", vec![Register(rt), Register(rs), Immediate(imm as i32)]),
+            9 => ("addiu", vec![Register(rt), Register(rs), Immediate(imm as i32)]),
+            10 => ("slti", vec![Register(rt), Register(rs), Immediate(imm as i32)]),
+            11 => ("sltiu", vec![Register(rt), Register(rs), Immediate(imm as i32)]),
+            12 => ("andi", vec![Register(rt), Register(rs), UnsignedImmediate(imm as u16)]),
+            13 => ("ori", vec![Register(rt), Register(rs), UnsignedImmediate(imm as u16)]),
+            14 => ("xori", vec![Register(rt), Register(rs), UnsignedImmediate(imm as u16)]),
+            15 => ("lui", vec![Register(rt), UnsignedImmediate(imm as u16)]),
+            16 => match rs
+            {
+                0 => ("mfc0", vec![Register(rt), Cp0Register(rd), Immediate(funct as i32 & 0b111)]),
+                4 => ("mtc0", vec![Register(rt), Cp0Register(rd), Immediate(funct as i32 & 0b111)]),
+                16 if funct == 0x18 => ("eret", vec![]),
+                _ => ("", vec![]),
+            },
+            32 => ("lb", vec![Register(rt), memory]),
+            33 => ("lh", vec![Register(rt), memory]),
+            34 => ("lw", vec![Register(rt), memory]),
+            36 => ("lbu", vec![Register(rt), memory]),
+            37 => ("lhu", vec![Register(rt), memory]),
+            40 => ("sb", vec![Register(rt), memory]),
+            41 => ("sh", vec![Register(rt), memory]),
+            43 => ("sw", vec![Register(rt), memory]),
+            _ => ("", vec![]),
+        }
+    };
+
+    DecodedInstruction
+    {
+        word,
+        opcode,
+        rs,
+        rt,
+        rd,
+        shift,
+        funct,
+        imm,
+        address: jump_address,
+        mnemonic: if mnemonic.is_empty() { None } else { Some(mnemonic) },
+        operands,
+    }
+}
+
+// one instruction as assembly text, address is where it lies (for branch and jump targets)
+pub fn disassemble(instruction: u32, address: u32) -> String
+{
+    decode(instruction, address).to_string()
+}
+
+// as disassemble, with the branch or jump target named when there is a symbol for it
+pub fn disassemble_with_symbols(instruction: u32, address: u32, symbols: &Symbols) -> String
+{
+    let decoded = decode(instruction, address);
+    let target = decoded.operands.iter().find_map(|operand| match operand
+    {
+        Operand::Target(target) => Some(*target),
+        _ => None,
+    });
+    match target.and_then(|target| symbols.location(target))
+    {
+        Some(location) => format!("{decoded} <{location}>"),
+        None => decoded.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn words_decode_into_fields_and_operands()
+    {
+        let decoded = decode(0x8bbf_0004, 0);
+        assert_eq!((decoded.opcode, decoded.rs, decoded.rt, decoded.imm), (34, 29, 31, 4));
+        assert_eq!(decoded.mnemonic, Some("lw"));
+        assert_eq!(decoded.operands, [Operand::Register(31), Operand::Memory { base: 29, offset: 4 }]);
+
+        let decoded = decode(0xFC00_0000, 0);
+        assert_eq!(decoded.mnemonic, None);
+        assert_eq!(decoded.to_string(), ".word 0xfc000000");
+    }
+
+    #[test]
+    fn instructions_print_as_assembly()
+    {
+        let listing = [
+            (0x0000_0000, "nop"),
+            (0x0109_5021, "addu $t2, $t0, $t1"),
+            (0x27bd_fff8, "addiu $sp, $sp, -8"),
+            (0xafa4_fffc, "sw $a0, -4($sp)"),
+            (0x3c01_1234, "lui $at, 0x1234"),
+            (0x3508_ffff, "ori $t0, $t0, 0xffff"),
+            (0x0009_40c0, "sll $t0, $t1, 3"),
+            (0x03e0_0008, "jr $ra"),
+            (0x4200_0018, "eret"),
+            (0x401a_6000, "mfc0 $k0, $12, 0"),
+            (0x409b_7000, "mtc0 $k1, $14, 0"),
+            (0x818b_0000, "lb $t3, 0($t4)"),
+        ];
+        for (word, text) in listing
+        {
+            assert_eq!(disassemble(word, 0x100), text);
+        }
+    }
+
+    #[test]
+    fn targets_are_absolute()
+    {
+        // branches count bytes from the next instruction
+        assert_eq!(disassemble(0x1100_0014, 0x10), "beq $t0, $zero, 0x28");
+        assert_eq!(disassemble(0x1500_fffc, 0x1000), "bne $t0, $zero, 0x1000");
+
+        // jumps keep the top bits of the next instruction's address
+        assert_eq!(disassemble(0x0c00_000a, 0x14), "jal 0x28");
+        assert_eq!(disassemble(0x0800_0010, 0x1000_0000), "j 0x10000040");
+        assert_eq!(disassemble(0x0bff_ffff, 0), "j 0xffffffc");
+    }
+}
@@ -46,6 +46,9 @@ impl Disk
 
         let file = File::open(&self.filename).expect("Could not open the disk");
         let mut buf: [u8; 4] = [0; 4];
+        #[cfg(target_os = "linux")]
+        file.read_at(&mut buf, sector_num).expect("Could not read from the disk");
+        #[cfg(target_os = "windows")]
         file.seek_read(&mut buf, sector_num).expect("Could not read from the disk");
 
         let sector = ((buf[0] as u32) << 24) |
@@ -72,6 +75,9 @@ impl Disk
 
         let buf: [u8; 4] = [(data >> 24) as u8, ((data >> 16) & 0xFF) as u8,
             ((data >> 8) & 0xFF) as u8, (data & 0xFF) as u8];
+        #[cfg(target_os = "linux")]
+        file.write_all_at(&buf, sector_num).expect("Could not write to the file");
+        #[cfg(target_os = "windows")]
         file.seek_write(&buf, sector_num).expect("Could not write to the file");
     }
 }
\ No newline at end of file
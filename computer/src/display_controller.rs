@@ -0,0 +1,255 @@
+use computer_config::PixelFormat;
+
+use crate::Computer;
+use crate::framebuffer::{self, Framebuffer};
+use crate::memory::Memory;
+use crate::screen::Screen;
+use crate::text_mode::{TextDisplay, TextScreen};
+use crate::video_layers::{VideoLayers, VIDEO_LAYERS_SIZE};
+
+impl Computer // display controller
+{
+    pub(crate) fn init_display_registers(memory: &mut Memory, width: u32, pixel_format: PixelFormat)
+    {
+        memory.write_byte(memory.display_mode_address() as usize, pixel_format as u8);
+
+        for mode in 0..PixelFormat::COUNT as u8
+        {
+            let format = PixelFormat::from_mode(mode).unwrap();
+            let address = memory.display_stride_address(format);
+            memory.write_word(address as usize, width * format.bytes_per_pixel());
+        }
+
+        let palette_address = memory.display_palette_address();
+        for (i, value) in framebuffer::default_palette().into_iter().enumerate()
+        {
+            memory.write_byte(palette_address as usize + i, value);
+        }
+    }
+
+    pub(crate) fn init_text_registers(memory: &mut Memory, cell_count: u32)
+    {
+        // boot straight into text mode, light grey on black, cursor in the corner
+        memory.write_byte(memory.text_select_address() as usize, TextDisplay::Replace as u8);
+        memory.write_byte(memory.text_cursor_address() as usize + 2, 1);
+
+        let cells = memory.text_cells_address();
+        for i in 0..cell_count
+        {
+            memory.write_half((cells + 2 * i) as usize, 0x2007); // space, attribute 0x07
+        }
+    }
+
+    fn display_format(&self) -> (PixelFormat, u32)
+    {
+        let mode = self.memory.read_byte(self.memory.display_mode_address() as usize);
+        let format = PixelFormat::from_mode(mode).unwrap_or(self.pixel_format); // the guest can write anything there
+
+        let stride = self.memory.read_word(self.memory.display_stride_address(format) as usize);
+
+        (format, stride)
+    }
+
+    pub fn framebuffer(&self) -> Framebuffer<'_>
+    {
+        let (format, stride) = self.display_format();
+        Framebuffer
+        {
+            data: self.front_page(),
+            width: self.width,
+            height: self.height,
+            stride,
+            format,
+            palette: self.palette(),
+        }
+    }
+
+    fn palette(&self) -> &[u8]
+    {
+        self.memory.bytes(self.memory.display_palette_address(), framebuffer::PALETTE_SIZE)
+    }
+
+    pub fn video_layers(&self) -> Option<VideoLayers<'_>>
+    {
+        let layers = VideoLayers
+        {
+            registers: self.memory.bytes(self.memory.video_layers_address(), VIDEO_LAYERS_SIZE),
+            vram: self.memory.vram(),
+            palette: self.palette(),
+        };
+
+        if layers.enabled() { Some(layers) } else { None }
+    }
+
+    pub fn screen(&self) -> Screen<'_>
+    {
+        Screen
+        {
+            framebuffer: self.framebuffer(),
+            layers: self.video_layers(),
+            text: self.text_screen(),
+        }
+    }
+
+    pub fn text_screen(&self) -> Option<TextScreen<'_>>
+    {
+        let (columns, rows) = self.text_mode?;
+
+        let select = self.memory.read_byte(self.memory.text_select_address() as usize);
+        let display = TextDisplay::from_select(select).unwrap_or(TextDisplay::Off); // no text for unknown values
+
+        let cursor_address = self.memory.text_cursor_address() as usize;
+        let cursor_x = self.memory.read_byte(cursor_address) as u32;
+        let cursor_y = self.memory.read_byte(cursor_address + 1) as u32;
+        let cursor_visible = self.memory.read_byte(cursor_address + 2) != 0;
+
+        Some(TextScreen
+        {
+            cells: self.memory.bytes(self.memory.text_cells_address(), 2 * columns * rows),
+            columns,
+            rows,
+            display,
+            cursor: if cursor_visible { Some((cursor_x, cursor_y)) } else { None },
+        })
+    }
+
+    fn page_size(&self) -> u32
+    {
+        self.memory.vram().len() as u32 / self.vram_pages
+    }
+
+    // the guest can write any page number, it wraps around the pages there are
+    fn front_page_index(&self) -> u32
+    {
+        self.memory.read_byte(self.memory.display_front_page_address() as usize) as u32 % self.vram_pages
+    }
+
+    fn front_page(&self) -> &[u8]
+    {
+        let page_size = self.page_size() as usize;
+        let start = self.front_page_index() as usize * page_size;
+        &self.memory.vram()[start..start + page_size]
+    }
+
+    // rows [from, to) of the front page written since the last call
+    pub fn take_dirty_rows(&mut self) -> Option<(u32, u32)>
+    {
+        let (_, stride) = self.display_format();
+        let (from, to) = self.memory.take_vram_dirty()?;
+
+        if self.video_layers().is_some()
+        {
+            return Some((0, self.height)); // tiles and sprites may take pixels from anywhere in VRAM
+        }
+
+        let page_start = self.front_page_index() * self.page_size();
+        let page_end = page_start + self.page_size();
+        if to <= page_start || from >= page_end
+        {
+            return None; // only the back buffer changed
+        }
+        let (from, to) = (from.max(page_start) - page_start, to.min(page_end) - page_start);
+
+        if stride == 0
+        {
+            return Some((0, self.height));
+        }
+
+        let to_row = to.div_ceil(stride).min(self.height);
+        Some(((from / stride).min(to_row), to_row))
+    }
+
+    // called by the frontend once per presented frame
+    pub fn vblank(&mut self)
+    {
+        let flip_address = self.memory.display_flip_address() as usize;
+        if self.memory.read_byte(flip_address) != 0
+        {
+            let front_address = self.memory.display_front_page_address() as usize;
+            let front_page = (self.front_page_index() + 1) % self.vram_pages;
+            self.memory.write_byte(front_address, front_page as u8);
+            self.memory.write_byte(flip_address, 0);
+        }
+
+        let counter_address = self.memory.display_frame_counter_address() as usize;
+        let frames = self.memory.read_word(counter_address);
+        self.memory.write_word(counter_address, frames.wrapping_add(1));
+
+        // status stays set until the guest clears it, which also acknowledges the interrupt
+        self.memory.write_byte(self.memory.display_vblank_address() as usize, 1);
+    }
+
+    pub(crate) fn vblank_interrupt(&self) -> bool
+    {
+        let status = self.memory.read_byte(self.memory.display_vblank_address() as usize);
+        let enabled = self.memory.read_byte(self.memory.display_vblank_interrupt_address() as usize);
+        status != 0 && enabled != 0
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::Computer;
+    use crate::text_mode::TextDisplay;
+    use computer_config::PixelFormat;
+
+    #[test]
+    fn bad_display_mode_shows_the_configured_format()
+    {
+        let mut computer = Computer::with_rom(&[], &["pixel_format=indexed8"]);
+        let mode_address = computer.memory.display_mode_address() as usize;
+        computer.memory.write_byte(mode_address, 0xFF);
+        let framebuffer = computer.framebuffer();
+        assert!(framebuffer.format == PixelFormat::Indexed8);
+        assert_eq!(framebuffer.stride, 64);
+    }
+
+    #[test]
+    fn front_page_wraps_around_the_pages()
+    {
+        let mut computer = Computer::with_rom(&[], &["double_buffer=on"]);
+        let page_size = computer.page_size() as usize;
+        let vram = computer.memory.vram_address() as usize;
+        computer.memory.write_byte(vram + page_size, 0xAB);
+
+        let front_address = computer.memory.display_front_page_address() as usize;
+        for (page, first_byte) in [(1, 0xAB), (2, 0), (3, 0xAB), (255, 0xAB)]
+        {
+            computer.memory.write_byte(front_address, page);
+            assert_eq!(computer.framebuffer().data.len(), page_size);
+            assert_eq!(computer.framebuffer().data[0], first_byte, "page {page}");
+        }
+
+        computer.memory.write_byte(front_address, 255);
+        computer.memory.write_byte(computer.memory.display_flip_address() as usize, 1);
+        computer.vblank();
+        assert_eq!(computer.memory.read_byte(front_address), 0);
+    }
+
+    #[test]
+    fn page_flips_redraw_the_whole_screen()
+    {
+        let mut computer = Computer::with_rom(&[], &["double_buffer=on"]);
+        computer.take_dirty_rows();
+        assert_eq!(computer.take_dirty_rows(), None);
+
+        computer.memory.write_byte(computer.memory.display_flip_address() as usize, 1);
+        computer.vblank();
+        assert_eq!(computer.take_dirty_rows(), Some((0, 48)));
+
+        computer.vblank();
+        assert_eq!(computer.take_dirty_rows(), None);
+        computer.memory.write_byte(computer.memory.display_front_page_address() as usize, 0);
+        assert_eq!(computer.take_dirty_rows(), Some((0, 48)));
+    }
+
+    #[test]
+    fn bad_text_select_turns_text_off()
+    {
+        let mut computer = Computer::with_rom(&[], &["text_mode=8x6"]);
+        assert!(computer.text_screen().unwrap().display == TextDisplay::Replace);
+        computer.memory.write_byte(computer.memory.text_select_address() as usize, 3);
+        assert!(computer.text_screen().unwrap().display == TextDisplay::Off);
+    }
+}
@@ -0,0 +1,432 @@
+// .debug_line of DWARF 2 to 5, 32-bit format, for mapping instruction addresses to source lines
+
+use std::sync::Arc;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+// a row per address where the line changes, line 0 where a sequence ends
+pub(crate) type LineRow = (u32, u32, Arc<str>); // address, line, file
+
+struct Cursor<'a>
+{
+    bytes: &'a [u8],
+    offset: usize,
+    big_endian: bool,
+}
+
+impl Cursor<'_>
+{
+    fn take(&mut self, len: usize) -> Option<&[u8]>
+    {
+        let bytes = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8>
+    {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16>
+    {
+        let bytes = self.take(2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&mut self) -> Option<u32>
+    {
+        let bytes = self.take(4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn uleb(&mut self) -> Option<u64>
+    {
+        let (mut value, mut shift) = (0u64, 0);
+        loop
+        {
+            let byte = self.u8()?;
+            if shift < 64
+            {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0
+            {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Option<i64>
+    {
+        let (mut value, mut shift) = (0i64, 0);
+        loop
+        {
+            let byte = self.u8()?;
+            if shift < 64
+            {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0
+            {
+                if shift < 64 && byte & 0x40 != 0
+                {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<&str>
+    {
+        let rest = self.bytes.get(self.offset..)?;
+        let end = rest.iter().position(|&byte| byte == 0)?;
+        self.offset += end + 1;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+}
+
+fn string_at(section: &[u8], offset: usize) -> Option<&str>
+{
+    let rest = section.get(offset..)?;
+    let end = rest.iter().position(|&byte| byte == 0)?;
+    std::str::from_utf8(&rest[..end]).ok()
+}
+
+fn join(directory: &str, name: &str) -> String
+{
+    if directory.is_empty() || name.starts_with('/')
+    {
+        name.to_string()
+    }
+    else
+    {
+        format!("{}/{name}", directory.trim_end_matches('/'))
+    }
+}
+
+// the attributes of a DWARF 5 directory or file entry that matter here: path and directory index
+fn read_entry(cursor: &mut Cursor, formats: &[(u64, u64)], strings: &[u8], line_strings: &[u8])
+    -> Option<(String, u64)>
+{
+    let (mut path, mut directory) = (String::new(), 0);
+    for &(content, form) in formats
+    {
+        let (text, number) = match form
+        {
+            DW_FORM_STRING => (cursor.string().map(str::to_string), None),
+            DW_FORM_LINE_STRP => (string_at(line_strings, cursor.u32()? as usize).map(str::to_string), None),
+            DW_FORM_STRP => (string_at(strings, cursor.u32()? as usize).map(str::to_string), None),
+            DW_FORM_UDATA => (None, Some(cursor.uleb()?)),
+            DW_FORM_DATA1 => (None, Some(cursor.u8()? as u64)),
+            DW_FORM_DATA2 => (None, Some(cursor.u16()? as u64)),
+            DW_FORM_DATA4 => (None, Some(cursor.u32()? as u64)),
+            DW_FORM_DATA8 =>
+            {
+                cursor.take(8)?;
+                (None, None)
+            },
+            DW_FORM_DATA16 =>
+            {
+                cursor.take(16)?;
+                (None, None)
+            },
+            DW_FORM_BLOCK =>
+            {
+                let len = cursor.uleb()? as usize;
+                cursor.take(len)?;
+                (None, None)
+            },
+            _ => return None,
+        };
+        match content
+        {
+            DW_LNCT_PATH => path = text?,
+            DW_LNCT_DIRECTORY_INDEX => directory = number?,
+            _ => {},
+        }
+    }
+    Some((path, directory))
+}
+
+fn read_entries(cursor: &mut Cursor, strings: &[u8], line_strings: &[u8]) -> Option<Vec<(String, u64)>>
+{
+    let format_count = cursor.u8()?;
+    let formats: Option<Vec<(u64, u64)>> = (0..format_count).map(|_| Some((cursor.uleb()?, cursor.uleb()?))).collect();
+    let formats = formats?;
+    let count = cursor.uleb()?;
+    (0..count).map(|_| read_entry(cursor, &formats, strings, line_strings)).collect()
+}
+
+// one unit from its header on, the cursor ends at the next unit
+fn parse_unit(cursor: &mut Cursor, strings: &[u8], line_strings: &[u8], rows: &mut Vec<LineRow>) -> Option<()>
+{
+    let length = cursor.u32()? as usize;
+    if length >= 0xFFFF_FFF0
+    {
+        return None; // 64-bit DWARF
+    }
+    let end = cursor.offset.checked_add(length)?;
+    let version = cursor.u16()?;
+    if !(2..=5).contains(&version)
+    {
+        return None;
+    }
+    if version >= 5
+    {
+        cursor.take(2)?; // address size, segment selector size
+    }
+    let header_length = cursor.u32()? as usize;
+    let program = cursor.offset.checked_add(header_length)?;
+    let min_instruction_length = cursor.u8()? as u64;
+    if version >= 4
+    {
+        cursor.u8()?; // operations per instruction, 1 for anything but VLIW
+    }
+    cursor.u8()?; // default is_stmt, every row counts here
+    let line_base = cursor.u8()? as i8 as i64;
+    let line_range = cursor.u8()?;
+    let opcode_base = cursor.u8()?;
+    if line_range == 0 || opcode_base == 0
+    {
+        return None;
+    }
+    let argument_counts: Vec<u8> = cursor.take(opcode_base as usize - 1)?.to_vec();
+
+    // before version 5 file 1 is the first listed and directory 0 is the unknown compilation directory
+    let mut files: Vec<Arc<str>> = Vec::new();
+    if version >= 5
+    {
+        let directories = read_entries(cursor, strings, line_strings)?;
+        for (path, directory) in read_entries(cursor, strings, line_strings)?
+        {
+            let directory = directories.get(directory as usize).map(|(path, _)| path.as_str()).unwrap_or("");
+            files.push(join(directory, &path).into());
+        }
+    }
+    else
+    {
+        let mut directories = vec![String::new()];
+        loop
+        {
+            match cursor.string()?
+            {
+                "" => break,
+                directory => directories.push(directory.to_string()),
+            }
+        }
+        files.push("".into());
+        loop
+        {
+            let name = cursor.string()?.to_string();
+            if name.is_empty()
+            {
+                break;
+            }
+            let directory = cursor.uleb()? as usize;
+            cursor.uleb()?; // modification time
+            cursor.uleb()?; // length
+            files.push(join(directories.get(directory).map(String::as_str).unwrap_or(""), &name).into());
+        }
+    }
+
+    cursor.offset = program;
+    let first_file = if version >= 5 { 0 } else { 1 };
+    let (mut address, mut file, mut line) = (0u64, first_file, 1i64);
+    let file_name = |files: &[Arc<str>], file: u64| files.get(file as usize).cloned().unwrap_or_else(|| "".into());
+    // the advances come from the file, programs that run the address or line out of range don't parse
+    let const_add_pc = (255 - opcode_base as u64) / line_range as u64 * min_instruction_length;
+    while cursor.offset < end
+    {
+        let opcode = cursor.u8()?;
+        if opcode >= opcode_base
+        {
+            let adjusted = (opcode - opcode_base) as u64;
+            address = address.checked_add(adjusted / line_range as u64 * min_instruction_length)?;
+            line = line.checked_add(line_base + (adjusted % line_range as u64) as i64)?;
+            rows.push((address as u32, line.max(1) as u32, file_name(&files, file)));
+            continue;
+        }
+        match opcode
+        {
+            0 =>
+            {
+                let len = cursor.uleb()? as usize;
+                let next = cursor.offset.checked_add(len)?;
+                match cursor.u8()?
+                {
+                    DW_LNE_END_SEQUENCE =>
+                    {
+                        rows.push((address as u32, 0, file_name(&files, file)));
+                        (address, file, line) = (0, first_file, 1);
+                    },
+                    DW_LNE_SET_ADDRESS if len == 5 => address = cursor.u32()? as u64,
+                    DW_LNE_DEFINE_FILE =>
+                    {
+                        let name = cursor.string()?.to_string();
+                        files.push(name.into());
+                    },
+                    _ => {},
+                }
+                cursor.offset = next;
+            },
+            DW_LNS_COPY => rows.push((address as u32, line.max(1) as u32, file_name(&files, file))),
+            DW_LNS_ADVANCE_PC => address = address.checked_add(cursor.uleb()?.checked_mul(min_instruction_length)?)?,
+            DW_LNS_ADVANCE_LINE => line = line.checked_add(cursor.sleb()?)?,
+            DW_LNS_SET_FILE => file = cursor.uleb()?,
+            DW_LNS_CONST_ADD_PC => address = address.checked_add(const_add_pc)?,
+            DW_LNS_FIXED_ADVANCE_PC => address = address.checked_add(cursor.u16()? as u64)?,
+            _ =>
+            {
+                // set column, negate stmt, basic block, prologue and epilogue, isa and unknown ones
+                for _ in 0..argument_counts[opcode as usize - 1]
+                {
+                    cursor.uleb()?;
+                }
+            },
+        }
+    }
+    cursor.offset = end;
+    Some(())
+}
+
+// rows of every unit, none if the section does not parse
+pub(crate) fn parse_line_table(debug_line: &[u8], strings: &[u8], line_strings: &[u8], big_endian: bool)
+    -> Option<Vec<LineRow>>
+{
+    let mut cursor = Cursor { bytes: debug_line, offset: 0, big_endian };
+    let mut rows = Vec::new();
+    while cursor.offset < debug_line.len()
+    {
+        parse_unit(&mut cursor, strings, line_strings, &mut rows)?;
+    }
+    Some(rows)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const DW_LNS_SET_COLUMN: u8 = 5;
+
+    // minimum instruction length 4, line base -5, line range 14, opcode base 13 with the standard argument counts
+    const PARAMETERS: [u8; 18] = [4, 1, 1, 0xFB, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+    fn u32_bytes(value: u32, big_endian: bool) -> [u8; 4]
+    {
+        if big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+    }
+
+    // a unit around header tables and a line number program, with the lengths filled in
+    fn unit(version: u16, big_endian: bool, tables: &[u8], program: &[u8]) -> Vec<u8>
+    {
+        let mut header = PARAMETERS.to_vec();
+        if version < 4
+        {
+            header.remove(1); // no operations per instruction
+        }
+        header.extend_from_slice(tables);
+
+        let mut body = if big_endian { version.to_be_bytes() } else { version.to_le_bytes() }.to_vec();
+        if version >= 5
+        {
+            body.extend_from_slice(&[4, 0]);
+        }
+        body.extend_from_slice(&u32_bytes(header.len() as u32, big_endian));
+        body.extend_from_slice(&header);
+        body.extend_from_slice(program);
+
+        let mut unit = u32_bytes(body.len() as u32, big_endian).to_vec();
+        unit.extend_from_slice(&body);
+        unit
+    }
+
+    fn set_address(address: u32, big_endian: bool) -> Vec<u8>
+    {
+        let mut bytes = vec![0, 5, DW_LNE_SET_ADDRESS];
+        bytes.extend_from_slice(&u32_bytes(address, big_endian));
+        bytes
+    }
+
+    fn rows(rows: &[(u32, u32, &str)]) -> Vec<LineRow>
+    {
+        rows.iter().map(|&(address, line, file)| (address, line, file.into())).collect()
+    }
+
+    #[test]
+    fn version_4_program_runs_every_opcode()
+    {
+        let tables = b"src\0\0main.s\0\x01\0\0other.s\0\0\0\0\0";
+        let mut program = set_address(0x100, true);
+        program.extend_from_slice(&[
+            20, // line + 2
+            33, // address + 4, line + 1
+            DW_LNS_ADVANCE_PC, 2,
+            DW_LNS_ADVANCE_LINE, 0x7E, // -2
+            DW_LNS_SET_COLUMN, 7,
+            DW_LNS_COPY,
+            DW_LNS_SET_FILE, 2,
+            DW_LNS_CONST_ADD_PC, // 17 instructions
+            DW_LNS_FIXED_ADVANCE_PC, 0, 4,
+            DW_LNS_COPY,
+            0, 1, DW_LNE_END_SEQUENCE,
+        ]);
+
+        let table = parse_line_table(&unit(4, true, tables, &program), &[], &[], true);
+        assert_eq!(table, Some(rows(&[(0x100, 3, "src/main.s"), (0x104, 4, "src/main.s"), (0x10c, 2, "src/main.s"),
+                                      (0x154, 2, "other.s"), (0x154, 0, "other.s")])));
+    }
+
+    #[test]
+    fn version_5_tables_use_entry_formats()
+    {
+        // directories as inline strings, files as .debug_line_str offsets and directory indexes
+        let mut tables = vec![1, 1, 0x08, 2];
+        tables.extend_from_slice(b"/work\0lib\0");
+        tables.extend_from_slice(&[2, 1, 0x1f, 2, 0x0f, 2]);
+        tables.extend_from_slice(&[0, 0, 0, 0, 0, 4, 0, 0, 0, 1]);
+        let mut program = set_address(0x200, false);
+        program.extend_from_slice(&[20, DW_LNS_SET_FILE, 1, 33, 0, 1, DW_LNE_END_SEQUENCE]);
+
+        let table = parse_line_table(&unit(5, false, &tables, &program), &[], b"a.s\0b.s\0", false);
+        assert_eq!(table, Some(rows(&[(0x200, 3, "/work/a.s"), (0x204, 4, "lib/b.s"), (0x204, 0, "lib/b.s")])));
+    }
+
+    #[test]
+    fn out_of_range_advances_do_not_parse()
+    {
+        let huge = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x3F]; // 2^62 - 1 either way
+        let tables = b"\0a.s\0\0\0\0\0";
+        for opcode in [DW_LNS_ADVANCE_PC, DW_LNS_ADVANCE_LINE]
+        {
+            let program: Vec<u8> = (0..3).flat_map(|_| [&[opcode][..], &huge].concat()).collect();
+            assert_eq!(parse_line_table(&unit(3, true, tables, &program), &[], &[], true), None);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use computer_config::PixelFormat;
+
+pub(crate) const PALETTE_SIZE: u32 = 256 * 3; // RGB888 entries
+
+// borrowed view of VRAM, valid until the next cycle
+pub struct Framebuffer<'a>
+{
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32, // bytes per row
+    pub format: PixelFormat,
+    pub palette: &'a [u8], // used by indexed modes
+}
+
+impl<'a> Framebuffer<'a>
+{
+    pub fn row(&self, y: u32) -> Option<&'a [u8]>
+    {
+        // the stride is guest written, rows past the end of the address space are outside VRAM too
+        let start = (y as usize).checked_mul(self.stride as usize)?;
+        let len = (self.width as usize).checked_mul(self.format.bytes_per_pixel() as usize)?;
+        self.data.get(start..start.checked_add(len)?)
+    }
+
+    // converts one row to RGBA, rows outside VRAM come out black
+    pub fn row_to_rgba(&self, y: u32, out: &mut [u8])
+    {
+        let row = match self.row(y)
+        {
+            Some(row) => row,
+            None => {
+                for pixel in out.chunks_exact_mut(4)
+                {
+                    pixel.copy_from_slice(&[0, 0, 0, 255]);
+                }
+                return;
+            }
+        };
+
+        let bpp = self.format.bytes_per_pixel() as usize;
+        for (pixel, src) in out.chunks_exact_mut(4).zip(row.chunks_exact(bpp))
+        {
+            let (r, g, b) = match self.format
+            {
+                PixelFormat::Rgb888 => (src[0], src[1], src[2]),
+                PixelFormat::Indexed8 => {
+                    let entry = 3 * src[0] as usize;
+                    (self.palette[entry], self.palette[entry + 1], self.palette[entry + 2])
+                }
+                PixelFormat::Rgb565 => {
+                    let value = ((src[0] as u16) << 8) | src[1] as u16;
+                    let r = ((value >> 11) & 0x1F) as u8;
+                    let g = ((value >> 5) & 0x3F) as u8;
+                    let b = (value & 0x1F) as u8;
+                    ((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2))
+                }
+                PixelFormat::Xrgb8888 => (src[1], src[2], src[3]),
+            };
+
+            pixel.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+}
+
+// RGB332 colour cube, so indexed mode shows something before the guest loads a palette
+pub(crate) fn default_palette() -> Vec<u8>
+{
+    let mut palette = Vec::with_capacity(PALETTE_SIZE as usize);
+    for i in 0..=255u32
+    {
+        palette.push((((i >> 5) & 7) * 255 / 7) as u8);
+        palette.push((((i >> 2) & 7) * 255 / 7) as u8);
+        palette.push(((i & 3) * 255 / 3) as u8);
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn rows_past_the_address_space_are_black()
+    {
+        let data = [0xFF; 16];
+        let palette = default_palette();
+        let framebuffer = Framebuffer
+        {
+            data: &data, width: 2, height: 2, stride: u32::MAX, format: PixelFormat::Rgb888, palette: &palette,
+        };
+        assert_eq!(framebuffer.row(0), Some(&data[..6]));
+        assert_eq!(framebuffer.row(u32::MAX), None);
+
+        let mut out = [0; 8];
+        framebuffer.row_to_rgba(1, &mut out);
+        assert_eq!(out, [0, 0, 0, 255, 0, 0, 0, 255]);
+        framebuffer.row_to_rgba(0, &mut out);
+        assert_eq!(out, [0xFF; 8]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use computer_config::Config;
+
+use crate::Computer;
+use crate::cpu_aux::TransferType;
+use crate::disassembler::{disassemble_with_symbols, REGISTER_NAMES};
+use crate::symbols::Symbols;
+
+// binary trace: "JTRC", version byte, then one record per retired instruction, little endian:
+//   u64 cycle, u32 pc, u32 instruction,
+//   u8 flags - register write count in bits 0-3, transfer type of the memory access in bits 4-7 (0 for none),
+//   per register write: u8 register (as in Computer::registers), u32 value,
+//   with a memory access: u32 address, u32 data
+const BINARY_MAGIC: &[u8; 4] = b"JTRC";
+const BINARY_VERSION: u8 = 1;
+
+// retired instructions written as text lines and/or binary records
+pub(crate) struct InstructionTrace
+{
+    text: Option<BufWriter<File>>,
+    binary: Option<BufWriter<File>>,
+    pc_range: Option<(u32, u32)>, // address, length
+    cycles: Option<(u64, u64)>, // first, last
+    symbols: Symbols,
+}
+
+impl InstructionTrace
+{
+    pub(crate) fn new(config: &Config, symbols: &Symbols) -> Option<InstructionTrace>
+    {
+        if config.trace().is_none() && config.trace_binary().is_none()
+        {
+            return None;
+        }
+
+        let text = config.trace().as_ref()
+            .map(|path| BufWriter::new(File::create(path).expect("Bad trace file")));
+        let mut binary = config.trace_binary().as_ref()
+            .map(|path| BufWriter::new(File::create(path).expect("Bad binary trace file")));
+        if let Some(binary) = &mut binary
+        {
+            binary.write_all(BINARY_MAGIC).and_then(|_| binary.write_all(&[BINARY_VERSION]))
+                .expect("Bad binary trace file");
+        }
+
+        Some(InstructionTrace
+        {
+            text,
+            binary,
+            pc_range: config.trace_pc(),
+            cycles: config.trace_cycles(),
+            symbols: symbols.clone(),
+        })
+    }
+
+    fn wanted(&self, cycle: u64, pc: u32) -> bool
+    {
+        let in_window = match self.cycles
+        {
+            Some((first, last)) => cycle >= first && cycle <= last,
+            None => true,
+        };
+        let in_range = match self.pc_range
+        {
+            Some((address, len)) => pc >= address && pc - address < len,
+            None => true,
+        };
+        in_window && in_range
+    }
+
+    // fetch is pc and instruction, access the load or store the instruction put on the bus
+    fn retire(&mut self, cycle: u64, fetch: (u32, u32), access: (TransferType, u32, u32), writes: &[(u8, i32)])
+    {
+        if !self.wanted(cycle, fetch.0)
+        {
+            return;
+        }
+
+        if let Some(text) = &mut self.text
+        {
+            if let Err(error) = Self::write_text(text, &self.symbols, cycle, fetch, access, writes)
+            {
+                eprintln!("Cannot write trace: {error}");
+                self.text = None;
+            }
+        }
+        if let Some(binary) = &mut self.binary
+        {
+            if let Err(error) = Self::write_binary(binary, cycle, fetch, access, writes)
+            {
+                eprintln!("Cannot write binary trace: {error}");
+                self.binary = None;
+            }
+        }
+    }
+
+    fn register_name(register: u8) -> &'static str
+    {
+        match register
+        {
+            33 => "hi",
+            34 => "lo",
+            n => REGISTER_NAMES[n as usize],
+        }
+    }
+
+    // with symbols the pc is followed by its function and offset
+    fn write_text(out: &mut impl Write, symbols: &Symbols, cycle: u64, (pc, instruction): (u32, u32),
+                  (transfer_type, address, data): (TransferType, u32, u32), writes: &[(u8, i32)]) -> io::Result<()>
+    {
+        let location = match symbols.location(pc)
+        {
+            Some(location) => format!(" {:<24}", format!("<{location}>")),
+            None => String::new(),
+        };
+        let disassembly = disassemble_with_symbols(instruction, pc, symbols);
+        let mut line = format!("{cycle:>12} {pc:08x}{location} {instruction:08x}  {disassembly:<28}");
+        for &(register, value) in writes
+        {
+            line += &format!(" {}={:08x}", Self::register_name(register), value as u32);
+        }
+        if !matches!(transfer_type, TransferType::NoTransfer)
+        {
+            let (access, width) = transfer_type.describe();
+            line += &format!(" {access} {width} {address:#010x}={data:08x}");
+        }
+        writeln!(out, "{}", line.trim_end())
+    }
+
+    fn write_binary(out: &mut impl Write, cycle: u64, (pc, instruction): (u32, u32),
+                    (transfer_type, address, data): (TransferType, u32, u32), writes: &[(u8, i32)]) -> io::Result<()>
+    {
+        let writes = &writes[..writes.len().min(15)];
+        let mut record = Vec::with_capacity(17 + 5 * writes.len() + 8);
+        record.extend_from_slice(&cycle.to_le_bytes());
+        record.extend_from_slice(&pc.to_le_bytes());
+        record.extend_from_slice(&instruction.to_le_bytes());
+        record.push(writes.len() as u8 | ((transfer_type as u8) << 4));
+        for &(register, value) in writes
+        {
+            record.push(register);
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+        if !matches!(transfer_type, TransferType::NoTransfer)
+        {
+            record.extend_from_slice(&address.to_le_bytes());
+            record.extend_from_slice(&data.to_le_bytes());
+        }
+        out.write_all(&record)
+    }
+
+    fn flush(&mut self)
+    {
+        for out in [&mut self.text, &mut self.binary].into_iter().flatten()
+        {
+            if let Err(error) = out.flush()
+            {
+                eprintln!("Cannot write trace: {error}");
+            }
+        }
+    }
+}
+
+impl Computer // instruction trace
+{
+    pub(crate) fn trace_instruction(&mut self, fetch: (u32, u32), access: (TransferType, u32, u32))
+    {
+        if let Some(trace) = &mut self.instruction_trace
+        {
+            trace.retire(self.cycles, fetch, access, self.cpu.reg_writes());
+        }
+    }
+
+    // the trace is buffered, frontends flush it every frame and before exiting
+    pub fn flush_trace(&mut self)
+    {
+        if let Some(trace) = &mut self.instruction_trace
+        {
+            trace.flush();
+        }
+    }
+}
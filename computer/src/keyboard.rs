@@ -1,26 +1,33 @@
-use device_query::{DeviceQuery, DeviceState};
-pub(crate) const KEY_COUNT: u8 = 96;
+use std::collections::VecDeque;
+
+pub(crate) const KEY_QUEUE_CAPACITY: usize = 64;
 
 pub(crate) struct Keyboard
 {
-
+    events: VecDeque<(u32, bool)>, // scancode, pressed
 }
 
 impl Keyboard
 {
     pub(crate) fn new() -> Self
     {
-        Self {}
+        Self
+        {
+            events: VecDeque::with_capacity(KEY_QUEUE_CAPACITY),
+        }
     }
 
-    pub(crate) fn get_keys(&self) -> Vec<u8>
+    pub(crate) fn push_event(&mut self, scancode: u32, pressed: bool)
     {
-        let mut key_codes: Vec<u8> = Vec::new();
-        let keys = DeviceState::new().get_keys();
-        for key in keys
+        if self.events.len() >= KEY_QUEUE_CAPACITY
         {
-            key_codes.push(key as u8);
+            return; // guest is not draining the queue, drop the newest event
         }
-        return key_codes;
+        self.events.push_back((scancode, pressed));
     }
-}
\ No newline at end of file
+
+    pub(crate) fn pop_event(&mut self) -> Option<(u32, bool)>
+    {
+        self.events.pop_front()
+    }
+}
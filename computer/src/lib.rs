@@ -5,15 +5,59 @@ mod memory;
 mod cpu_aux;
 mod disk;
 mod keyboard;
+mod text_input;
 mod mouse;
+mod framebuffer;
+mod text_mode;
+mod video_layers;
+mod screen;
+mod display_controller;
+mod blitter;
+mod audio;
+mod psg;
+mod disassembler;
+mod debugger;
+mod bus_trace;
+mod screenshot;
+mod instruction_trace;
+mod memory_map;
+mod symbols;
+mod dwarf;
+mod profiler;
+mod coverage;
+mod reports;
 
 use cpu::CPU;
-use memory::Memory;
+use memory::{read_image, Memory};
 use disk::Disk;
 use keyboard::Keyboard;
+use text_input::TextInput;
 use mouse::Mouse;
-
-use crate::cpu_aux::TransferType;
+use audio::Audio;
+use psg::Psg;
+use instruction_trace::InstructionTrace;
+use profiler::Profiler;
+use coverage::Coverage;
+pub use mouse::MouseButton;
+pub use framebuffer::Framebuffer;
+pub use text_mode::{TextDisplay, TextScreen};
+pub use video_layers::VideoLayers;
+pub use screen::Screen;
+pub use disassembler::{decode, disassemble, disassemble_with_symbols, DecodedInstruction, Operand, REGISTER_NAMES};
+pub use bus_trace::BusTrace;
+pub use memory_map::memory_map;
+pub use symbols::Symbols;
+pub use debugger::ExecutingPc;
+pub use reports::Reports;
+pub use computer_config::{WatchAction, WatchKind};
+pub use computer_config::PixelFormat;
+
+use crate::cpu_aux::{Interrupt, TransferType};
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use computer_config::Config;
 
@@ -25,68 +69,179 @@ pub struct Computer
     cpu: CPU,
     disk: Disk,
     keyboard: Keyboard,
+    text_input: TextInput,
     mouse: Mouse,
+    audio: Audio,
+    psg: Psg,
     //
 
     tt_bus: TransferType,
     addres_bus: u32,
     data_bus: u32,
+
+    cycles: u64,
+    breakpoints: HashSet<u32>,
+    watchpoints: Vec<(u32, u32, WatchKind, WatchAction)>, // address, length, kind, action
+    watch_hit: Option<(WatchKind, u32)>,
+    trace: Option<BusTrace>,
+    instruction_trace: Option<InstructionTrace>,
+    reports: Reports,
+    halted: bool,
+    symbols: Symbols,
+    executing: Arc<AtomicU32>, // pc of the current instruction, for crash reports
+
+    width: u32,
+    height: u32,
+    text_mode: Option<(u32, u32)>,
+    vram_pages: u32,
+    pixel_format: PixelFormat, // configured, shown while the mode register holds no format
 }
 
 impl Computer
 {
     pub fn new(config: Config) -> Computer
     {
-        Self::make_computer(
-            config.rom_filename(),
-            config.program_filename(),
-            config.disk_filename(),
-            config.disk_size(),
-            config.memory_size(),
-            config.vram_size(),
-        )
+        Self::make_computer(&config)
     }
 }
 
 impl Computer
 {
-    fn make_computer
-    (rom_filename: &Option<String>, program_filename: &Option<String>, disk_filename: &String, disk_size: u64,
-     memory_size: u32, vram_size: u32)
-     -> Computer
+    fn make_computer(config: &Config) -> Computer
     {
-
-
-        let cpu = CPU::new();
-        let memory = Memory::new(rom_filename, program_filename, memory_size, vram_size);
-        let disk = Disk::new(disk_size, &disk_filename);
+        let (width, height) = (config.width(), config.height());
+        let text_mode = config.text_mode();
+
+        let mut cpu = CPU::new();
+        let audio = Audio::new(config.sample_rate(), config.audio_channels(), config.audio_buffer_size(),
+                               config.cycles_per_second());
+        let psg = Psg::new(config.sample_rate());
+        let vram_pages = config.vram_pages();
+        let mut memory = Self::make_memory(config, read_image(config.rom_filename()),
+                                           &read_image(config.program_filename()));
+        Self::init_display_registers(&mut memory, width, config.pixel_format());
+        Self::init_audio_registers(&mut memory, &audio);
+        if let Some((columns, rows)) = text_mode
+        {
+            Self::init_text_registers(&mut memory, columns * rows);
+        }
+        let disk = Disk::new(config.disk_size(), config.disk_filename());
         let keyboard = Keyboard::new();
+        let text_input = TextInput::new();
         let mouse = Mouse::new();
+        let symbols = config.symbols().iter().fold(Symbols::default(), |symbols, path|
+            match Symbols::load(Path::new(path))
+            {
+                Ok(loaded) => symbols.merge(&loaded),
+                Err(error) => panic!("Bad symbol file {path}: {error}"),
+            });
+        cpu.set_symbols(symbols.clone());
+        let instruction_trace = InstructionTrace::new(config, &symbols);
+        cpu.set_record_writes(instruction_trace.is_some());
+        let reports = Self::make_reports(Profiler::new(config, &symbols), Coverage::new(config, &symbols, &memory));
         Computer
         {
             cpu,
             memory,
             disk,
             keyboard,
+            text_input,
             mouse,
+            audio,
+            psg,
             tt_bus: TransferType::NoTransfer,
             addres_bus: 0,
-            data_bus: 0
+            data_bus: 0,
+            cycles: 0,
+            breakpoints: HashSet::new(),
+            watchpoints: config.watchpoints().clone(),
+            watch_hit: None,
+            trace: if config.trace_buffer() > 0 { Some(BusTrace::new(config.trace_buffer(), &symbols)) } else { None },
+            instruction_trace,
+            reports,
+            halted: false,
+            symbols,
+            executing: Arc::new(AtomicU32::new(0)),
+            width,
+            height,
+            text_mode,
+            vram_pages,
+            pixel_format: config.pixel_format(),
         }
     }
 
 
 
+    // regions sized for the config around the given images, the memory map builds one with a blank ROM
+    pub(crate) fn make_memory(config: &Config, rom: Vec<u8>, program: &[u8]) -> Memory
+    {
+        let text_buffer_size = match config.text_mode()
+        {
+            None => 0,
+            Some((columns, rows)) => text_mode::TEXT_REGISTERS_SIZE + 2 * columns * rows,
+        };
+        let audio_buffer_size = audio::AUDIO_REGISTERS_SIZE + 2 * config.audio_buffer_size();
+        Memory::new(rom, program, config.memory_size(), config.vram_size() * config.vram_pages(), text_buffer_size,
+                    audio_buffer_size)
+    }
+
     pub fn cycle(&mut self)
     {
+        if self.halted
+        {
+            return;
+        }
+
+        let observed = self.trace.is_some() || !self.watchpoints.is_empty();
+
         self.cpu_tick(); // IF
+        if observed
+        {
+            self.observe_bus(true);
+        }
+        let fetch = (self.addres_bus, self.data_bus);
+        let interrupted = self.cpu.interrupt_return();
+        self.executing.store(self.addres_bus, Ordering::Relaxed);
         self.cpu_tick(); // DEXE
+        if observed
+        {
+            self.observe_bus(false); // loads and stores go out in this phase
+        }
+        let access = (self.tt_bus, self.addres_bus, self.data_bus);
         self.cpu_tick(); // MEM
         self.cpu_tick(); // WB
+        if self.instruction_trace.is_some()
+        {
+            self.trace_instruction(fetch, access);
+        }
+        if self.reporting()
+        {
+            self.report_instruction(fetch, access.0, interrupted);
+        }
 
         self.disk_controller();
         self.keyboard_controller();
+        self.text_input_controller();
         self.mouse_controller();
+        self.blitter_controller();
+        self.audio_controller();
+        self.interrupt_controller();
+
+        self.cycles += 1;
+        self.check_breakpoint();
+    }
+
+    pub fn cycles(&self) -> u64
+    {
+        self.cycles
+    }
+
+    // reports collected over the whole run, frontends call this when the machine stops; dropping the computer
+    // writes whatever is left, a panic hook can write the rest through reports()
+    pub fn write_reports(&mut self)
+    {
+        self.flush_trace();
+        self.reports.write();
     }
 
     #[allow(unused)]
@@ -99,6 +254,14 @@ impl Computer
     }
 }
 
+impl Drop for Computer
+{
+    fn drop(&mut self)
+    {
+        self.write_reports();
+    }
+}
+
 impl Computer // communication with devices (CPU, disk, display etc.)
 {
     fn cpu_tick(&mut self)
@@ -136,29 +299,6 @@ impl Computer // communication with devices (CPU, disk, display etc.)
         //println!("Transfer Type: {}, Address: {} Data: {}", self.tt_bus as u8, self.addres_bus, self.data_bus);
     }
 
-    pub fn get_vram(&self) -> Vec<u8>
-    {
-        let mut vram: Vec<u8> = Vec::new();
-
-        let from = self.memory.vram_start();
-        let to = self.memory.vram_end();
-
-        //#[cfg(debug_assertions)]
-        //println!("from {from} to {to}");
-
-        for address in from..to
-        {
-            let subpixel = self.memory.read_byte(address as usize);
-            vram.push(subpixel);
-        }
-
-        //#[cfg(debug_assertions)]
-        //println!("vram size: {}", vram.len());
-
-        return vram;
-    }
-
-
     pub fn get_disk_buffer(&self) -> (u8, u64, u32)
     {
         let start = self.memory.disk_buffer_transfer_type_address() as usize;
@@ -209,21 +349,78 @@ impl Computer // communication with devices (CPU, disk, display etc.)
         self.memory.write_byte(tt_addr as usize, 0); // no transfer
     }
 
+    pub fn key_event(&mut self, scancode: u32, pressed: bool)
+    {
+        self.keyboard.push_event(scancode, pressed);
+    }
+
     fn keyboard_controller(&mut self)
     {
-        let from = self.memory.keyboard_buffer_address();
-        let to = self.memory.keyboard_buffer_end_address();
-        for i in from..to
+        // ready byte is set by the controller and cleared by the guest after reading the event
+        let ready_addr = self.memory.keyboard_buffer_address();
+        if self.memory.read_byte(ready_addr as usize) != 0
         {
-            self.memory.write_byte(i as usize, 0);
+            return; // previous event not consumed yet
         }
 
-        let keys_pushed = self.keyboard.get_keys();
-        for key in keys_pushed
+        let (scancode, pressed) = match self.keyboard.pop_event()
         {
-            let address = self.memory.keyboard_buffer_address() + key as u32;
-            self.memory.write_byte(address as usize, 1);
+            Some(event) => event,
+            None => return,
+        };
+
+        let pressed_addr = self.memory.keyboard_buffer_pressed_address();
+        let scancode_addr = self.memory.keyboard_buffer_scancode_address();
+
+        self.memory.write_byte(pressed_addr as usize, pressed as u8);
+        self.memory.write_word(scancode_addr as usize, scancode);
+        self.memory.write_byte(ready_addr as usize, 1);
+    }
+
+    pub fn char_event(&mut self, c: char)
+    {
+        self.text_input.push_char(c);
+    }
+
+    fn text_input_controller(&mut self)
+    {
+        let available_addr = self.memory.text_input_available_address();
+        let pop_addr = self.memory.text_input_pop_address();
+        let char_addr = self.memory.text_input_char_address();
+
+        if self.memory.read_byte(pop_addr as usize) != 0
+        {
+            let code_point = match self.text_input.pop_char()
+            {
+                Some(c) => c as u32,
+                None => 0,
+            };
+            self.memory.write_word(char_addr as usize, code_point);
+            self.memory.write_byte(pop_addr as usize, 0);
         }
+
+        let available = self.text_input.available().min(u8::MAX as usize);
+        self.memory.write_byte(available_addr as usize, available as u8);
+    }
+
+    pub fn mouse_moved(&mut self, x: u32, y: u32)
+    {
+        self.mouse.move_to(x, y);
+    }
+
+    pub fn mouse_button(&mut self, button: MouseButton, pressed: bool)
+    {
+        self.mouse.set_button(button, pressed);
+    }
+
+    pub fn mouse_wheel(&mut self, dx: i32, dy: i32)
+    {
+        self.mouse.scroll(dx, dy);
+    }
+
+    pub fn mouse_inside(&mut self, inside: bool)
+    {
+        self.mouse.set_inside(inside);
     }
 
     fn mouse_controller(&mut self)
@@ -232,12 +429,71 @@ impl Computer // communication with devices (CPU, disk, display etc.)
         let y_addr = x_addr + 4;
         let lmb_addr = y_addr + 4;
         let rmb_addr = lmb_addr + 1;
+        let mmb_addr = rmb_addr + 1;
+        let inside_addr = mmb_addr + 1;
+        let wheel_x_addr = inside_addr + 1;
+        let wheel_y_addr = wheel_x_addr + 4;
 
-        let (x, y, lmb, rmb) = self.mouse.get_mouse();
+        let (x, y, lmb, rmb, mmb, inside) = self.mouse.get_mouse();
 
         self.memory.write_word(x_addr as usize, x);
         self.memory.write_word(y_addr as usize, y);
         self.memory.write_byte(lmb_addr as usize, lmb as u8);
         self.memory.write_byte(rmb_addr as usize, rmb as u8);
+        self.memory.write_byte(mmb_addr as usize, mmb as u8);
+        self.memory.write_byte(inside_addr as usize, inside as u8);
+
+        // wheel registers accumulate until the guest clears them
+        let (dx, dy) = self.mouse.take_wheel();
+        if dx != 0
+        {
+            let wheel_x = self.memory.read_word(wheel_x_addr as usize) as i32;
+            self.memory.write_word(wheel_x_addr as usize, wheel_x.wrapping_add(dx) as u32);
+        }
+        if dy != 0
+        {
+            let wheel_y = self.memory.read_word(wheel_y_addr as usize) as i32;
+            self.memory.write_word(wheel_y_addr as usize, wheel_y.wrapping_add(dy) as u32);
+        }
+    }
+}
+
+impl Computer // interrupts
+{
+    fn interrupt_controller(&mut self)
+    {
+        let vblank = self.vblank_interrupt();
+        self.cpu.set_interrupt(Interrupt::VBlank, vblank);
+
+        let blitter = self.blitter_interrupt();
+        self.cpu.set_interrupt(Interrupt::Blitter, blitter);
+
+        let audio = self.audio_interrupt();
+        self.cpu.set_interrupt(Interrupt::Audio, audio);
+    }
+}
+
+#[cfg(test)]
+impl Computer // test machines
+{
+    // a 64x48 machine booting the ROM words, its disk and ROM files in the temporary directory
+    pub(crate) fn with_rom(rom: &[u32], options: &[&str]) -> Computer
+    {
+        static MACHINES: AtomicU32 = AtomicU32::new(0);
+        let name = format!("jupiter-test-{}-{}", std::process::id(), MACHINES.fetch_add(1, Ordering::Relaxed));
+        let directory = std::env::temp_dir();
+        let rom_path = directory.join(format!("{name}.rom"));
+        let disk_path = directory.join(format!("{name}.disk"));
+        let bytes: Vec<u8> = rom.iter().flat_map(|word| word.to_be_bytes()).collect();
+        std::fs::write(&rom_path, bytes).expect("Bad test ROM");
+
+        let mut args: Vec<String> = vec!["jupiter".to_string(), rom_path.display().to_string(), "none".to_string(),
+                                         disk_path.display().to_string(), "4K".to_string(), "1M".to_string(),
+                                         "64".to_string(), "48".to_string()];
+        args.extend(options.iter().map(|option| option.to_string()));
+        let computer = Computer::new(Config::from_args(args));
+        let _ = std::fs::remove_file(rom_path);
+        let _ = std::fs::remove_file(disk_path);
+        computer
     }
 }
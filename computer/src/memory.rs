@@ -1,10 +1,21 @@
 use std::fs::File;
 use std::io::Read;
-use crate::keyboard;
-use keyboard::KEY_COUNT;
+
+use computer_config::PixelFormat;
+use crate::framebuffer::PALETTE_SIZE;
+use crate::text_mode::TEXT_REGISTERS_SIZE;
+use crate::video_layers::VIDEO_LAYERS_SIZE;
+use crate::blitter::BLITTER_BUFFER_SIZE;
+use crate::audio::AUDIO_REGISTERS_SIZE;
+use crate::psg::PSG_BUFFER_SIZE;
 
 const DISK_BUFFER_SIZE: u32 = 1 + 8 + 4;
-const MOUSE_BUFFER_SIZE: u32 = 4 + 4 + 1 + 1;
+const KEYBOARD_BUFFER_SIZE: u32 = 1 + 1 + 4; // ready, pressed, scancode
+const TEXT_INPUT_BUFFER_SIZE: u32 = 1 + 1 + 4; // available, pop, code point
+const MOUSE_BUFFER_SIZE: u32 = 4 + 4 + 1 + 1 + 1 + 1 + 4 + 4; // x, y, lmb, rmb, mmb, inside, wheel x, wheel y
+// flip request, front page, vblank, vblank interrupt enable, frame counter, screenshot request
+const DISPLAY_SYNC_SIZE: u32 = 1 + 1 + 1 + 1 + 4 + 1;
+const DISPLAY_BUFFER_SIZE: u32 = 1 + 4 * PixelFormat::COUNT as u32 + PALETTE_SIZE; // mode, stride per mode, palette
 
 pub(crate) struct Memory
 {
@@ -14,81 +25,109 @@ pub(crate) struct Memory
     rom: (u32, u32),
     disk_buffer: (u32, u32),
     keyboard_buffer: (u32, u32),
+    text_input_buffer: (u32, u32),
     mouse_buffer: (u32, u32),
+    blitter_buffer: (u32, u32),
+    audio_buffer: (u32, u32),
+    psg_buffer: (u32, u32),
+    display_sync: (u32, u32),
+    display_buffer: (u32, u32),
+    video_layers: (u32, u32),
+    text_buffer: (u32, u32),
     vram: (u32, u32),
+    program: u32,
+    vram_dirty: Option<(u32, u32)>, // written byte range, relative to vram start
+}
+
+// contents of a ROM or program image file, empty without one
+pub(crate) fn read_image(filename: &Option<String>) -> Vec<u8>
+{
+    let mut data = Vec::new();
+    if let Some(filename) = filename
+    {
+        let mut file = File::open(filename).unwrap();
+        file.read_to_end(&mut data).unwrap();
+    }
+    data
 }
 
 impl Memory
 {
-    pub fn new(rom_filename: &Option<String>, program_filename: &Option<String>, size: u32, vram_size: u32)
+    pub fn new(rom: Vec<u8>, program: &[u8], size: u32, vram_size: u32, text_buffer_size: u32,
+               audio_buffer_size: u32)
         -> Memory
     {
-        let mut data: Vec<u8> = Vec::new();
-
-        let rom_size = match rom_filename
-        {
-            None => 0,
-            Some(filename) => {
-
-                //eprintln!("Rom FILEname: {}", filename);
-                let mut rom_file = File::open(filename).unwrap();
-                rom_file.read_to_end(&mut data).unwrap();
-
-                let file_size = rom_file.metadata().unwrap().len() as u32;
-                file_size
-            },
-        };
+        let rom_size = rom.len() as u32;
+        let mut data = rom;
 
         let (rom_start, rom_end) = (0, rom_size);
 
         let (disk_buffer_start, disk_buffer_end) =
-            (rom_end as u32, rom_end as u32 + DISK_BUFFER_SIZE);
+            (rom_end, rom_end + DISK_BUFFER_SIZE);
         let disk_buffer_size = disk_buffer_end - disk_buffer_start;
-        for _ in 0..disk_buffer_size
-        {
-            data.push(0);
-        }
+        data.resize(data.len() + disk_buffer_size as usize, 0);
 
         let (keyboard_buffer_start, keyboard_buffer_end) =
-            (disk_buffer_end as u32, disk_buffer_end as u32 + KEY_COUNT as u32);
+            (disk_buffer_end, disk_buffer_end + KEYBOARD_BUFFER_SIZE);
         let keyboard_buffer_size = keyboard_buffer_end - keyboard_buffer_start;
-        for _ in 0..keyboard_buffer_size
-        {
-            data.push(0);
-        }
+        data.resize(data.len() + keyboard_buffer_size as usize, 0);
+
+        let (text_input_buffer_start, text_input_buffer_end) =
+            (keyboard_buffer_end, keyboard_buffer_end + TEXT_INPUT_BUFFER_SIZE);
+        let text_input_buffer_size = text_input_buffer_end - text_input_buffer_start;
+        data.resize(data.len() + text_input_buffer_size as usize, 0);
 
         let (mouse_buffer_start, mouse_buffer_end) =
-            (keyboard_buffer_end, keyboard_buffer_end + MOUSE_BUFFER_SIZE);
+            (text_input_buffer_end, text_input_buffer_end + MOUSE_BUFFER_SIZE);
         let mouse_buffer_size = mouse_buffer_end - mouse_buffer_start;
-        for _ in 0..mouse_buffer_size
-        {
-            data.push(0);
-        }
+        data.resize(data.len() + mouse_buffer_size as usize, 0);
 
-        let (vram_start, vram_end) = (mouse_buffer_end, mouse_buffer_end + vram_size);
-        let vram_size = vram_end - vram_start;
-        for _ in 0..vram_size
-        {
-            data.push(0);
-        }
+        let (blitter_buffer_start, blitter_buffer_end) =
+            (mouse_buffer_end, mouse_buffer_end + BLITTER_BUFFER_SIZE);
+        let blitter_buffer_size = blitter_buffer_end - blitter_buffer_start;
+        data.resize(data.len() + blitter_buffer_size as usize, 0);
 
-        let program_size = match program_filename
-        {
-            None => 0,
-            Some(filename) => {
-                let mut program_file = File::open(filename).unwrap();
-                program_file.read_to_end(&mut data).unwrap();
-                let file_size = program_file.metadata().unwrap().len() as u32;
-                file_size
-            }
-        };
+        let (audio_buffer_start, audio_buffer_end) =
+            (blitter_buffer_end, blitter_buffer_end + audio_buffer_size);
+        data.resize(data.len() + audio_buffer_size as usize, 0);
+
+        let (psg_buffer_start, psg_buffer_end) =
+            (audio_buffer_end, audio_buffer_end + PSG_BUFFER_SIZE);
+        let psg_buffer_size = psg_buffer_end - psg_buffer_start;
+        data.resize(data.len() + psg_buffer_size as usize, 0);
+
+        let (display_sync_start, display_sync_end) =
+            (psg_buffer_end, psg_buffer_end + DISPLAY_SYNC_SIZE);
+        let display_sync_size = display_sync_end - display_sync_start;
+        data.resize(data.len() + display_sync_size as usize, 0);
+
+        let (display_buffer_start, display_buffer_end) =
+            (display_sync_end, display_sync_end + DISPLAY_BUFFER_SIZE);
+        let display_buffer_size = display_buffer_end - display_buffer_start;
+        data.resize(data.len() + display_buffer_size as usize, 0);
 
-        let program_end = vram_end + program_size;
+        let (video_layers_start, video_layers_end) =
+            (display_buffer_end, display_buffer_end + VIDEO_LAYERS_SIZE);
+        let video_layers_size = video_layers_end - video_layers_start;
+        data.resize(data.len() + video_layers_size as usize, 0);
+
+        let (text_buffer_start, text_buffer_end) =
+            (video_layers_end, video_layers_end + text_buffer_size);
+        data.resize(data.len() + text_buffer_size as usize, 0);
+
+        // device regions have odd sizes, VRAM and the program start on a word so the CPU can use them
+        let vram_start = text_buffer_end.next_multiple_of(4);
+        data.resize(vram_start as usize, 0);
+        let vram_end = vram_start + vram_size;
+        data.resize(data.len() + vram_size as usize, 0);
+
+        let program_start = vram_end.next_multiple_of(4);
+        data.resize(program_start as usize, 0);
+        data.extend_from_slice(program);
+
+        let program_end = program_start + program.len() as u32;
         let size_left = size - program_end;
-        for _ in 0..size_left
-        {
-            data.push(0);
-        }
+        data.resize(data.len() + size_left as usize, 0);
 
         Memory
         {
@@ -97,44 +136,220 @@ impl Memory
             rom: (rom_start, rom_end),
             disk_buffer: (disk_buffer_start, disk_buffer_end),
             keyboard_buffer: (keyboard_buffer_start, keyboard_buffer_end),
+            text_input_buffer: (text_input_buffer_start, text_input_buffer_end),
             mouse_buffer: (mouse_buffer_start, mouse_buffer_end),
+            blitter_buffer: (blitter_buffer_start, blitter_buffer_end),
+            audio_buffer: (audio_buffer_start, audio_buffer_end),
+            psg_buffer: (psg_buffer_start, psg_buffer_end),
+            display_sync: (display_sync_start, display_sync_end),
+            display_buffer: (display_buffer_start, display_buffer_end),
+            video_layers: (video_layers_start, video_layers_end),
+            text_buffer: (text_buffer_start, text_buffer_end),
             vram: (vram_start, vram_end),
+            program: program_start,
+            vram_dirty: Some((0, vram_size)), // first frame has to be drawn whole
         }
     }
 
     pub fn disk_buffer_transfer_type_address(&self) -> u32
     {
-        return self.disk_buffer.0;
+        self.disk_buffer.0
     }
 
     pub fn disk_buffer_data_address(&self) -> u32
     {
-        return self.disk_buffer.0 + 9;
+        self.disk_buffer.0 + 9
     }
 
     pub fn keyboard_buffer_address(&self) -> u32
     {
-        return self.keyboard_buffer.0;
+        self.keyboard_buffer.0
+    }
+
+    pub fn keyboard_buffer_pressed_address(&self) -> u32
+    {
+        self.keyboard_buffer.0 + 1
+    }
+
+    pub fn keyboard_buffer_scancode_address(&self) -> u32
+    {
+        self.keyboard_buffer.0 + 2
+    }
+
+    pub fn text_input_available_address(&self) -> u32
+    {
+        self.text_input_buffer.0
     }
 
-    pub fn keyboard_buffer_end_address(&self) -> u32
+    pub fn text_input_pop_address(&self) -> u32
     {
-        return self.keyboard_buffer.1;
+        self.text_input_buffer.0 + 1
+    }
+
+    pub fn text_input_char_address(&self) -> u32
+    {
+        self.text_input_buffer.0 + 2
     }
 
     pub fn mouse_buffer_address(&self) -> u32
     {
-        return self.mouse_buffer.0;
+        self.mouse_buffer.0
+    }
+
+    pub fn blitter_buffer_address(&self) -> u32
+    {
+        self.blitter_buffer.0
+    }
+
+    pub fn audio_buffer_address(&self) -> u32
+    {
+        self.audio_buffer.0
+    }
+
+    pub fn audio_samples_address(&self) -> u32
+    {
+        self.audio_buffer.0 + AUDIO_REGISTERS_SIZE
+    }
+
+    pub fn psg_buffer_address(&self) -> u32
+    {
+        self.psg_buffer.0
+    }
+
+    pub fn display_flip_address(&self) -> u32
+    {
+        self.display_sync.0
+    }
+
+    pub fn display_front_page_address(&self) -> u32
+    {
+        self.display_sync.0 + 1
+    }
+
+    pub fn display_vblank_address(&self) -> u32
+    {
+        self.display_sync.0 + 2
+    }
+
+    pub fn display_vblank_interrupt_address(&self) -> u32
+    {
+        self.display_sync.0 + 3
+    }
+
+    pub fn display_frame_counter_address(&self) -> u32
+    {
+        self.display_sync.0 + 4
+    }
+
+    pub fn display_screenshot_address(&self) -> u32
+    {
+        self.display_sync.0 + 8
+    }
+
+    pub fn display_mode_address(&self) -> u32
+    {
+        self.display_buffer.0
+    }
+
+    pub fn display_stride_address(&self, format: PixelFormat) -> u32
+    {
+        self.display_buffer.0 + 1 + 4 * format as u32
     }
 
-    pub fn vram_start(&self) -> u32
+    pub fn display_palette_address(&self) -> u32
     {
-        return self.vram.0;
+        self.display_buffer.0 + 1 + 4 * PixelFormat::COUNT as u32
     }
 
-    pub fn vram_end(&self) -> u32
+    pub fn video_layers_address(&self) -> u32
     {
-        return self.vram.1;
+        self.video_layers.0
+    }
+
+    pub fn text_select_address(&self) -> u32
+    {
+        self.text_buffer.0
+    }
+
+    pub fn text_cursor_address(&self) -> u32
+    {
+        self.text_buffer.0 + 1 // x, y, visible
+    }
+
+    pub fn text_cells_address(&self) -> u32
+    {
+        self.text_buffer.0 + TEXT_REGISTERS_SIZE
+    }
+
+    pub fn rom_size(&self) -> u32
+    {
+        self.rom.1 - self.rom.0
+    }
+
+    pub fn vram_address(&self) -> u32
+    {
+        self.vram.0
+    }
+
+    pub fn program_address(&self) -> u32
+    {
+        self.program
+    }
+
+    pub fn size(&self) -> u32
+    {
+        self.size
+    }
+
+    pub fn bytes(&self, address: u32, len: u32) -> &[u8]
+    {
+        &self.data[address as usize..(address + len) as usize]
+    }
+
+    pub fn vram(&self) -> &[u8]
+    {
+        &self.data[self.vram.0 as usize..self.vram.1 as usize]
+    }
+
+    pub fn take_vram_dirty(&mut self) -> Option<(u32, u32)>
+    {
+        self.vram_dirty.take()
+    }
+
+    pub fn mark_all_vram_dirty(&mut self)
+    {
+        self.vram_dirty = Some((0, self.vram.1 - self.vram.0));
+    }
+
+    fn mark_vram_dirty(&mut self, address: usize, len: u32)
+    {
+        let address = address as u32;
+        // display registers, video layers and text buffer lie right before vram
+        if address + len > self.display_buffer.0 && address < self.text_buffer.1
+        {
+            // mode, stride, palette, layers or text changed, the whole picture is affected
+            self.mark_all_vram_dirty();
+            return;
+        }
+        let front_page = self.display_sync.0 + 1;
+        if address <= front_page && address + len > front_page
+        {
+            // a flip shows another page without any VRAM writes
+            self.mark_all_vram_dirty();
+            return;
+        }
+        if address + len <= self.vram.0 || address >= self.vram.1
+        {
+            return;
+        }
+
+        let from = address.max(self.vram.0) - self.vram.0;
+        let to = (address + len).min(self.vram.1) - self.vram.0;
+        self.vram_dirty = match self.vram_dirty
+        {
+            None => Some((from, to)),
+            Some((dirty_from, dirty_to)) => Some((dirty_from.min(from), dirty_to.max(to))),
+        };
     }
 
     fn address_check(&self, address: usize)
@@ -169,7 +384,7 @@ impl Memory
     fn write_address_check(&self, address: usize)
     {
         let address = address as u32;
-        if address >= self.rom.0 && address <= self.rom.1
+        if address >= self.rom.0 && address < self.rom.1
         {
             panic!("Memory read only");
         }
@@ -177,11 +392,9 @@ impl Memory
 
     pub fn write_byte(&mut self, address: usize, data: u8)
     {
-        #[cfg(debug_assertions)]
-        println!("Writing {data} to {address}");
-
         self.address_check(address);
         self.write_address_check(address);
+        self.mark_vram_dirty(address, 1);
         self.data[address] = data;
     }
 
@@ -189,6 +402,7 @@ impl Memory
     {
         self.address_check(address);
         self.write_address_check(address);
+        self.mark_vram_dirty(address, 2);
         self.data[address] = (data >> 8) as u8;
         self.data[address + 1] = (data & 0xFF) as u8;
     }
@@ -197,9 +411,79 @@ impl Memory
     {
         self.address_check(address);
         self.write_address_check(address);
+        self.mark_vram_dirty(address, 4);
         self.data[address] = (data >> 24) as u8;
         self.data[address + 1] = ((data >> 16) & 0xFF) as u8;
         self.data[address + 2] = ((data >> 8) & 0xFF) as u8;
         self.data[address + 3] = (data & 0xFF) as u8;
     }
+
+    pub fn write_bytes(&mut self, address: u32, data: &[u8])
+    {
+        if data.is_empty()
+        {
+            return;
+        }
+        let len = data.len() as u32;
+        self.address_check((address + len - 1) as usize);
+        self.write_range_check(address, len);
+        self.mark_vram_dirty(address as usize, len);
+        self.data[address as usize..(address + len) as usize].copy_from_slice(data);
+    }
+
+    // no read only check, for the debugger
+    pub fn patch(&mut self, address: u32, data: &[u8])
+    {
+        self.mark_vram_dirty(address as usize, data.len() as u32);
+        self.data[address as usize..address as usize + data.len()].copy_from_slice(data);
+    }
+
+    // overlapping ranges are handled like memmove
+    pub fn copy(&mut self, from: u32, to: u32, len: u32)
+    {
+        if len == 0
+        {
+            return;
+        }
+        self.address_check((from + len - 1) as usize);
+        self.address_check((to + len - 1) as usize);
+        self.write_range_check(to, len);
+        self.mark_vram_dirty(to as usize, len);
+        self.data.copy_within(from as usize..(from + len) as usize, to as usize);
+    }
+
+    fn write_range_check(&self, address: u32, len: u32)
+    {
+        if address < self.rom.1 && address + len > self.rom.0
+        {
+            panic!("Memory read only");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::audio::AUDIO_REGISTERS_SIZE;
+
+    fn memory_with_rom(rom_size: usize) -> Memory
+    {
+        Memory::new(vec![0; rom_size], &[], 0x10000, 256, 0, AUDIO_REGISTERS_SIZE)
+    }
+
+    #[test]
+    #[should_panic(expected = "Memory read only")]
+    fn last_rom_byte_is_read_only()
+    {
+        memory_with_rom(16).write_byte(15, 1);
+    }
+
+    #[test]
+    fn byte_after_rom_is_writable()
+    {
+        let mut memory = memory_with_rom(16);
+        memory.write_byte(16, 1);
+        assert_eq!(memory.read_byte(16), 1);
+    }
 }
@@ -0,0 +1,34 @@
+use computer_config::Config;
+
+use crate::Computer;
+
+// start of each memory region of the machine described by config, with a ROM of rom_size bytes;
+// regions follow the ROM (VRAM and the program word aligned), so tools can lay out images without running the machine
+pub fn memory_map(config: &Config, rom_size: u32) -> Vec<(&'static str, u32)>
+{
+    let memory = Computer::make_memory(config, vec![0; rom_size as usize], &[]);
+
+    let mut map = vec![
+        ("DISK_BUFFER", memory.disk_buffer_transfer_type_address()),
+        ("KEYBOARD_BUFFER", memory.keyboard_buffer_address()),
+        ("TEXT_INPUT_BUFFER", memory.text_input_available_address()),
+        ("MOUSE_BUFFER", memory.mouse_buffer_address()),
+        ("BLITTER_BUFFER", memory.blitter_buffer_address()),
+        ("AUDIO_BUFFER", memory.audio_buffer_address()),
+        ("AUDIO_SAMPLES", memory.audio_samples_address()),
+        ("PSG_BUFFER", memory.psg_buffer_address()),
+        ("DISPLAY_SYNC", memory.display_flip_address()),
+        ("DISPLAY_BUFFER", memory.display_mode_address()),
+        ("DISPLAY_PALETTE", memory.display_palette_address()),
+        ("VIDEO_LAYERS", memory.video_layers_address()),
+    ];
+    if config.text_mode().is_some()
+    {
+        map.push(("TEXT_BUFFER", memory.text_select_address()));
+        map.push(("TEXT_CELLS", memory.text_cells_address()));
+    }
+    map.push(("VRAM", memory.vram_address()));
+    map.push(("PROGRAM", memory.program_address()));
+    map.push(("MEMORY_SIZE", memory.size()));
+    map
+}
@@ -1,39 +1,79 @@
-extern crate winapi;
-use winapi::shared::windef::LPPOINT;
-use winapi::shared::windef::POINT;
-use winapi::um::winuser::{GetCursorPos, VK_LBUTTON, VK_RBUTTON};
-use winapi::um::winuser::GetKeyState;
+#[derive(Clone, Copy)]
+pub enum MouseButton
+{
+    Left,
+    Right,
+    Middle,
+}
 
 pub(crate) struct Mouse
 {
+    x: u32,
+    y: u32,
+    lmb: bool,
+    rmb: bool,
+    mmb: bool,
+    inside: bool,
 
+    // wheel movement not yet delivered to the guest
+    wheel_x: i32,
+    wheel_y: i32,
 }
 
 impl Mouse
 {
     pub(crate) fn new() -> Self
     {
-        Self{}
+        Self
+        {
+            x: 0,
+            y: 0,
+            lmb: false,
+            rmb: false,
+            mmb: false,
+            inside: false,
+            wheel_x: 0,
+            wheel_y: 0,
+        }
+    }
+
+    pub(crate) fn move_to(&mut self, x: u32, y: u32)
+    {
+        self.x = x;
+        self.y = y;
+    }
+
+    pub(crate) fn set_button(&mut self, button: MouseButton, pressed: bool)
+    {
+        match button
+        {
+            MouseButton::Left => self.lmb = pressed,
+            MouseButton::Right => self.rmb = pressed,
+            MouseButton::Middle => self.mmb = pressed,
+        }
+    }
+
+    pub(crate) fn scroll(&mut self, dx: i32, dy: i32)
+    {
+        self.wheel_x = self.wheel_x.wrapping_add(dx);
+        self.wheel_y = self.wheel_y.wrapping_add(dy);
+    }
+
+    pub(crate) fn set_inside(&mut self, inside: bool)
+    {
+        self.inside = inside;
     }
 
-    pub(crate) fn get_mouse(&self) -> (u32, u32, bool, bool)
+    pub(crate) fn get_mouse(&self) -> (u32, u32, bool, bool, bool, bool)
     {
-        let (x_pos, y_pos) = unsafe {
-            let mut point: POINT = POINT
-            {
-                x: 0,
-                y: 0,
-            };
-            let p: LPPOINT = &mut point as *mut POINT;
-            GetCursorPos(p);
-            let x = (*p).x as u32;
-            let y = (*p).y as u32;
-            (x, y)
-        };
-
-        let lmb = unsafe{GetKeyState(VK_LBUTTON) < 0};
-        let rmb = unsafe{GetKeyState(VK_RBUTTON) < 0};
-
-        (x_pos, y_pos, lmb, rmb)
+        (self.x, self.y, self.lmb, self.rmb, self.mmb, self.inside)
     }
-}
\ No newline at end of file
+
+    pub(crate) fn take_wheel(&mut self) -> (i32, i32)
+    {
+        let wheel = (self.wheel_x, self.wheel_y);
+        self.wheel_x = 0;
+        self.wheel_y = 0;
+        wheel
+    }
+}
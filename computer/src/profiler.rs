@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use computer_config::Config;
+
+use crate::cpu_aux::TransferType;
+use crate::disassembler::disassemble_with_symbols;
+use crate::symbols::Symbols;
+
+// deeper stacks come from recursion that never returns or from jal used as a jump, the oldest calls are dropped
+const MAX_DEPTH: usize = 256;
+
+// every instruction retires in one cycle, so instructions are cycles too
+#[derive(Clone, Copy, Default)]
+struct Counts
+{
+    instructions: u64,
+    accesses: u64,
+}
+
+impl Counts
+{
+    fn add(&mut self, other: &Counts)
+    {
+        self.instructions += other.instructions;
+        self.accesses += other.accesses;
+    }
+}
+
+// what the last instruction does to the call stack once the next one shows where it went
+#[derive(Clone, Copy)]
+enum Pending
+{
+    Nothing,
+    Call(u32), // return address
+    Return,
+}
+
+// exact counts per instruction address, and cycles per call stack tracked through jal, jalr, jr $ra and eret
+pub(crate) struct Profiler
+{
+    report: Option<File>, // files are none once written
+    folded: Option<File>,
+    symbols: Symbols,
+    instructions: HashMap<u32, (u32, u32, Counts)>, // pc -> instruction, entry of the function it ran in, counts
+    stack: Vec<(u32, u32)>, // function entry, return address; the first is where the run started
+    stacks: HashMap<Vec<u32>, u64>, // function entries -> cycles
+    stack_cycles: u64, // cycles of the current stack not yet in stacks
+    pending: Pending,
+}
+
+impl Profiler
+{
+    pub(crate) fn new(config: &Config, symbols: &Symbols) -> Option<Profiler>
+    {
+        if config.profile().is_none() && config.profile_folded().is_none()
+        {
+            return None;
+        }
+
+        // created up front so a bad path fails before a long run rather than after
+        let report = config.profile().as_ref().map(|path| File::create(path).expect("Bad profile file"));
+        let folded = config.profile_folded().as_ref()
+            .map(|path| File::create(path).expect("Bad folded profile file"));
+
+        Some(Profiler
+        {
+            report,
+            folded,
+            symbols: symbols.clone(),
+            instructions: HashMap::new(),
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            stack_cycles: 0,
+            pending: Pending::Nothing,
+        })
+    }
+
+    // moves the cycles of the current stack into stacks, before it changes
+    fn close_stack(&mut self)
+    {
+        if self.stack_cycles > 0
+        {
+            let entries = self.stack.iter().map(|&(entry, _)| entry).collect();
+            *self.stacks.entry(entries).or_default() += self.stack_cycles;
+            self.stack_cycles = 0;
+        }
+    }
+
+    fn call(&mut self, entry: u32, return_address: u32)
+    {
+        self.close_stack();
+        if self.stack.len() >= MAX_DEPTH
+        {
+            self.stack.remove(1);
+        }
+        self.stack.push((entry, return_address));
+    }
+
+    // a return to somewhere no call left from is a computed jump, the stack stays
+    fn return_to(&mut self, address: u32)
+    {
+        if let Some(depth) = self.stack.iter().skip(1).rposition(|&(_, return_address)| return_address == address)
+        {
+            self.close_stack();
+            self.stack.truncate(depth + 1);
+        }
+    }
+
+    // interrupted holds where the interrupted code continues when pc is the first instruction of the handler
+    pub(crate) fn retire(&mut self, (pc, instruction): (u32, u32), transfer_type: TransferType,
+                         interrupted: Option<u32>)
+    {
+        if self.stack.is_empty()
+        {
+            self.stack.push((pc, pc));
+        }
+
+        // the previous jump landed where this instruction is, or where the handler returns to
+        let landed = interrupted.unwrap_or(pc);
+        match self.pending
+        {
+            Pending::Nothing => {},
+            Pending::Call(return_address) => self.call(landed, return_address),
+            Pending::Return => self.return_to(landed),
+        }
+        if let Some(return_address) = interrupted
+        {
+            self.call(pc, return_address);
+        }
+
+        self.stack_cycles += 1;
+
+        let function = self.stack[self.stack.len() - 1].0;
+        let (_, _, counts) = self.instructions.entry(pc).or_insert((instruction, function, Counts::default()));
+        counts.instructions += 1;
+        if !matches!(transfer_type, TransferType::NoTransfer)
+        {
+            counts.accesses += 1;
+        }
+
+        let opcode = instruction >> 26;
+        let rs = (instruction >> 21) & 0x1F;
+        let funct = instruction & 0x3F;
+        self.pending = match (opcode, rs, funct)
+        {
+            (3, _, _) | (0, _, 9) => Pending::Call(pc.wrapping_add(4)), // jal, jalr
+            (0, 31, 8) | (16, 16, 0x18) => Pending::Return, // jr $ra, eret
+            _ => Pending::Nothing,
+        };
+    }
+
+    // functions are what the call stack entered, local labels inside them do not split them
+    fn function_name(&self, entry: u32) -> String
+    {
+        self.symbols.location(entry).unwrap_or_else(|| format!("{entry:#010x}"))
+    }
+
+    fn write_report(&self, out: &mut impl Write) -> io::Result<()>
+    {
+        let mut total = Counts::default();
+        let mut functions: HashMap<String, Counts> = HashMap::new();
+        for (_, entry, counts) in self.instructions.values()
+        {
+            total.add(counts);
+            functions.entry(self.function_name(*entry)).or_default().add(counts);
+        }
+        let percent = |instructions: u64| 100.0 * instructions as f64 / total.instructions.max(1) as f64;
+
+        writeln!(out, "{} instructions in as many cycles, {} memory accesses", total.instructions, total.accesses)?;
+
+        writeln!(out, "\nfunctions\n{:>12} {:>7} {:>12}  function", "instructions", "%", "accesses")?;
+        let mut functions: Vec<(String, Counts)> = functions.into_iter().collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| b.instructions.cmp(&a.instructions).then(a_name.cmp(b_name)));
+        for (name, counts) in &functions
+        {
+            writeln!(out, "{:>12} {:>6.2}% {:>12}  {name}", counts.instructions, percent(counts.instructions),
+                     counts.accesses)?;
+        }
+
+        writeln!(out, "\ninstructions\n{:>8} {:>12} {:>7} {:>12}  instruction", "pc", "instructions", "%",
+                 "accesses")?;
+        let mut instructions: Vec<_> = self.instructions.iter().collect();
+        instructions.sort_by_key(|(&pc, _)| pc);
+        for (&pc, (instruction, _, counts)) in instructions
+        {
+            let location = match self.symbols.location(pc)
+            {
+                Some(location) => format!("<{location}> "),
+                None => String::new(),
+            };
+            writeln!(out, "{pc:08x} {:>12} {:>6.2}% {:>12}  {location}{}", counts.instructions,
+                     percent(counts.instructions), counts.accesses,
+                     disassemble_with_symbols(*instruction, pc, &self.symbols))?;
+        }
+        Ok(())
+    }
+
+    // one "outer;inner cycles" line per call stack
+    fn write_folded(&self, out: &mut impl Write) -> io::Result<()>
+    {
+        let mut lines: Vec<(String, u64)> = self.stacks.iter()
+            .map(|(entries, &cycles)|
+                {
+                    let names: Vec<String> = entries.iter().map(|&entry| self.function_name(entry)).collect();
+                    (names.join(";"), cycles)
+                })
+            .collect();
+        lines.sort();
+        for (stack, cycles) in lines
+        {
+            writeln!(out, "{stack} {cycles}")?;
+        }
+        Ok(())
+    }
+
+    // once, later calls find nothing to write
+    pub(crate) fn write(&mut self)
+    {
+        self.close_stack();
+        if let Some(file) = self.report.take()
+        {
+            let mut out = BufWriter::new(file);
+            if let Err(error) = self.write_report(&mut out).and_then(|_| out.flush())
+            {
+                eprintln!("Cannot write profile: {error}");
+            }
+        }
+        if let Some(file) = self.folded.take()
+        {
+            let mut out = BufWriter::new(file);
+            if let Err(error) = self.write_folded(&mut out).and_then(|_| out.flush())
+            {
+                eprintln!("Cannot write folded profile: {error}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const NOP: u32 = 0;
+    const JR_RA: u32 = 0x03e0_0008;
+    const ERET: u32 = 0x4200_0018;
+    const LW: u32 = 0x8ba8_0000;
+
+    fn jal(target: u32) -> u32
+    {
+        (3 << 26) | (target >> 2)
+    }
+
+    #[test]
+    fn call_stacks_follow_jal_jr_ra_and_interrupts()
+    {
+        let symbols = Symbols::from_map("100 main\n180 handler\n200 f\n208 f.loop\n300 g\n");
+        let mut profiler = Profiler
+        {
+            report: None,
+            folded: None,
+            symbols,
+            instructions: HashMap::new(),
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            stack_cycles: 0,
+            pending: Pending::Nothing,
+        };
+
+        // pc, instruction, interrupted
+        let run = [
+            (0x100, NOP, None), (0x104, jal(0x200), None),
+            (0x200, NOP, None), (0x204, jal(0x300), None),
+            (0x300, LW, None), (0x304, JR_RA, None),
+            (0x208, NOP, None), (0x20c, JR_RA, None),
+            (0x108, NOP, None),
+            (0x180, NOP, Some(0x10c)), (0x184, ERET, None),
+            (0x10c, NOP, None),
+        ];
+        for (pc, instruction, interrupted) in run
+        {
+            let transfer_type = if instruction == LW { TransferType::ReadWord } else { TransferType::NoTransfer };
+            profiler.retire((pc, instruction), transfer_type, interrupted);
+        }
+        profiler.close_stack();
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 4\nmain;f 4\nmain;f;g 2\nmain;handler 2\n");
+
+        let mut report = Vec::new();
+        profiler.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "12 instructions in as many cycles, 1 memory accesses");
+        // the local label stays part of f
+        assert!(lines.iter().any(|line| line.ends_with("  f") && line.trim_start().starts_with("4 ")), "{report}");
+        assert!(!lines.iter().any(|line| line.ends_with("  f.loop")), "{report}");
+        assert!(lines.iter().any(|line| line.starts_with("00000300") && line.contains("<g> lw $t0, 0($sp)")),
+                "{report}");
+    }
+}
@@ -0,0 +1,295 @@
+use crate::Computer;
+
+pub(crate) const TONE_CHANNELS: u32 = 4;
+pub(crate) const CHANNEL_COUNT: u32 = TONE_CHANNELS + 1; // the last one is noise
+pub(crate) const CHANNEL_SIZE: u32 = 16;
+pub(crate) const PSG_BUFFER_SIZE: u32 = CHANNEL_COUNT * CHANNEL_SIZE;
+
+// channel registers
+const WAVEFORM: u32 = 0; // tone channels only
+const VOLUME: u32 = 1;
+const GATE: u32 = 2; // 1 starts attack, 0 starts release
+const DUTY: u32 = 3; // square high time out of 256, 0 means half
+const FREQUENCY: u32 = 4; // Hz, u16, noise channel: shift register clock
+const ATTACK: u32 = 6; // ms, u16
+const DECAY: u32 = 8; // ms, u16
+const RELEASE: u32 = 10; // ms, u16
+const SUSTAIN: u32 = 12; // level out of 255
+
+// waveforms, any other value is silent
+const SQUARE: u8 = 0;
+const TRIANGLE: u8 = 1;
+const SAWTOOTH: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage
+{
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Clone, Copy)]
+struct Channel
+{
+    phase: f32,
+    level: f32, // envelope, 0 to 1
+    stage: Stage,
+    gate: bool,
+}
+
+// tone and noise channels, mixed into the audio device's output
+pub(crate) struct Psg
+{
+    sample_rate: f32,
+    channels: [Channel; CHANNEL_COUNT as usize],
+    noise: u16, // 15-bit LFSR
+}
+
+impl Psg
+{
+    pub(crate) fn new(sample_rate: u32) -> Psg
+    {
+        let channel = Channel { phase: 0.0, level: 0.0, stage: Stage::Idle, gate: false };
+        Psg
+        {
+            sample_rate: sample_rate as f32,
+            channels: [channel; CHANNEL_COUNT as usize],
+            noise: 1,
+        }
+    }
+
+    // envelope step per sample for a segment lasting ms milliseconds
+    fn envelope_step(&self, ms: u16) -> f32
+    {
+        if ms == 0 { 1.0 } else { 1000.0 / (ms as f32 * self.sample_rate) }
+    }
+}
+
+impl Computer // sound generator
+{
+    fn psg_byte(&self, channel: u32, register: u32) -> u8
+    {
+        let address = self.memory.psg_buffer_address() + channel * CHANNEL_SIZE + register;
+        self.memory.read_byte(address as usize)
+    }
+
+    fn psg_half(&self, channel: u32, register: u32) -> u16
+    {
+        let address = self.memory.psg_buffer_address() + channel * CHANNEL_SIZE + register;
+        let bytes = self.memory.bytes(address, 2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn psg_envelope(&mut self, index: u32) -> f32
+    {
+        let gate = self.psg_byte(index, GATE) != 0;
+        let sustain = self.psg_byte(index, SUSTAIN) as f32 / 255.0;
+        let attack = self.psg.envelope_step(self.psg_half(index, ATTACK));
+        let decay = self.psg.envelope_step(self.psg_half(index, DECAY));
+        let release = self.psg.envelope_step(self.psg_half(index, RELEASE));
+
+        let channel = &mut self.psg.channels[index as usize];
+        if gate && !channel.gate
+        {
+            channel.stage = Stage::Attack;
+        }
+        else if !gate && channel.gate
+        {
+            channel.stage = Stage::Release;
+        }
+        channel.gate = gate;
+
+        match channel.stage
+        {
+            Stage::Idle => channel.level = 0.0,
+            Stage::Attack =>
+            {
+                channel.level += attack;
+                if channel.level >= 1.0
+                {
+                    channel.level = 1.0;
+                    channel.stage = Stage::Decay;
+                }
+            },
+            Stage::Decay =>
+            {
+                channel.level -= decay;
+                if channel.level <= sustain
+                {
+                    channel.level = sustain;
+                    channel.stage = Stage::Sustain;
+                }
+            },
+            Stage::Sustain => channel.level = sustain,
+            Stage::Release =>
+            {
+                channel.level -= release;
+                if channel.level <= 0.0
+                {
+                    channel.level = 0.0;
+                    channel.stage = Stage::Idle;
+                }
+            },
+        }
+
+        channel.level
+    }
+
+    // one mixed sample, called at the audio sample rate
+    pub(crate) fn psg_sample(&mut self) -> i16
+    {
+        let mut mix = 0.0;
+
+        for index in 0..CHANNEL_COUNT
+        {
+            let level = self.psg_envelope(index);
+            let volume = self.psg_byte(index, VOLUME) as f32 / 255.0;
+            if level == 0.0 || volume == 0.0
+            {
+                continue;
+            }
+
+            let step = self.psg_half(index, FREQUENCY) as f32 / self.psg.sample_rate;
+            let duty = match self.psg_byte(index, DUTY)
+            {
+                0 => 0.5,
+                duty => duty as f32 / 256.0,
+            };
+            let waveform = self.psg_byte(index, WAVEFORM);
+
+            let channel = &mut self.psg.channels[index as usize];
+            channel.phase += step;
+            let wrapped = channel.phase >= 1.0;
+            channel.phase = channel.phase.fract();
+            let phase = channel.phase;
+
+            let value = if index == TONE_CHANNELS
+            {
+                if wrapped
+                {
+                    let bit = (self.psg.noise ^ (self.psg.noise >> 1)) & 1;
+                    self.psg.noise = (self.psg.noise >> 1) | (bit << 14);
+                }
+                if self.psg.noise & 1 != 0 { 1.0 } else { -1.0 }
+            }
+            else
+            {
+                match waveform
+                {
+                    SQUARE => if phase < duty { 1.0 } else { -1.0 },
+                    TRIANGLE => 4.0 * (phase - 0.5).abs() - 1.0,
+                    SAWTOOTH => 2.0 * phase - 1.0,
+                    _ => 0.0,
+                }
+            };
+
+            mix += value * level * volume;
+        }
+
+        // every channel gets an equal share so the sum never clips
+        (mix / CHANNEL_COUNT as f32 * i16::MAX as f32) as i16
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // a machine sampling at 1 kHz, so envelope times in ms are samples
+    fn machine() -> Computer
+    {
+        Computer::with_rom(&[], &["sample_rate=1000"])
+    }
+
+    fn set(computer: &mut Computer, channel: u32, register: u32, bytes: &[u8])
+    {
+        let address = computer.memory.psg_buffer_address() + channel * CHANNEL_SIZE + register;
+        computer.memory.write_bytes(address, bytes);
+    }
+
+    fn assert_levels(computer: &mut Computer, expected: &[f32])
+    {
+        for &level in expected
+        {
+            let actual = computer.psg_envelope(0);
+            assert!((actual - level).abs() < 1e-4, "{actual} instead of {level}");
+        }
+    }
+
+    #[test]
+    fn envelope_goes_through_attack_decay_sustain_and_release()
+    {
+        let mut computer = machine();
+        set(&mut computer, 0, ATTACK, &4u16.to_be_bytes());
+        set(&mut computer, 0, DECAY, &2u16.to_be_bytes());
+        set(&mut computer, 0, RELEASE, &4u16.to_be_bytes());
+        set(&mut computer, 0, SUSTAIN, &[128]);
+        let sustain = 128.0 / 255.0;
+        assert_levels(&mut computer, &[0.0]);
+
+        set(&mut computer, 0, GATE, &[1]);
+        assert_levels(&mut computer, &[0.25, 0.5, 0.75, 1.0, sustain, sustain, sustain]);
+        assert!(computer.psg.channels[0].stage == Stage::Sustain);
+
+        set(&mut computer, 0, GATE, &[0]);
+        assert_levels(&mut computer, &[sustain - 0.25, sustain - 0.5, 0.0, 0.0]);
+        assert!(computer.psg.channels[0].stage == Stage::Idle);
+
+        // a gate shorter than the attack releases from where the attack got to
+        set(&mut computer, 0, GATE, &[1]);
+        assert_levels(&mut computer, &[0.25]);
+        set(&mut computer, 0, GATE, &[0]);
+        assert_levels(&mut computer, &[0.0]);
+        set(&mut computer, 0, GATE, &[1]);
+        assert_levels(&mut computer, &[0.25, 0.5]);
+    }
+
+    #[test]
+    fn noise_steps_once_per_period_through_all_15_bit_states()
+    {
+        let mut computer = machine();
+        let noise = TONE_CHANNELS;
+        set(&mut computer, noise, VOLUME, &[255]);
+        set(&mut computer, noise, SUSTAIN, &[255]);
+        set(&mut computer, noise, FREQUENCY, &500u16.to_be_bytes());
+        set(&mut computer, noise, GATE, &[1]);
+
+        // half the sample rate shifts every other sample, the output follows the low bit
+        let mut states = Vec::new();
+        for _ in 0..8
+        {
+            let sample = computer.psg_sample();
+            assert_eq!(sample > 0, computer.psg.noise & 1 != 0);
+            states.push(computer.psg.noise);
+        }
+        assert_eq!(states, [1, 0x4000, 0x4000, 0x2000, 0x2000, 0x1000, 0x1000, 0x0800]);
+
+        let mut steps = 4;
+        while computer.psg.noise != 1
+        {
+            computer.psg_sample();
+            computer.psg_sample();
+            steps += 1;
+        }
+        assert_eq!(steps, 32767);
+    }
+
+    #[test]
+    fn undefined_waveforms_are_silent()
+    {
+        let mut computer = machine();
+        set(&mut computer, 0, VOLUME, &[255]);
+        set(&mut computer, 0, SUSTAIN, &[255]);
+        set(&mut computer, 0, FREQUENCY, &100u16.to_be_bytes());
+        set(&mut computer, 0, WAVEFORM, &[SAWTOOTH + 1]);
+        set(&mut computer, 0, GATE, &[1]);
+        assert!((0..20).all(|_| computer.psg_sample() == 0));
+
+        set(&mut computer, 0, WAVEFORM, &[SQUARE]);
+        assert!(computer.psg_sample() != 0);
+    }
+}
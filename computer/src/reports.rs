@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex, TryLockError};
+
+use crate::Computer;
+use crate::coverage::Coverage;
+use crate::cpu_aux::TransferType;
+use crate::profiler::Profiler;
+
+// the profile and coverage of a run, shared with the frontend so a panic hook can write them: release builds abort
+// on a panic and nothing is dropped
+#[derive(Clone, Default)]
+pub struct Reports
+{
+    profiler: Option<Arc<Mutex<Profiler>>>,
+    coverage: Option<Arc<Mutex<Coverage>>>,
+}
+
+impl Reports
+{
+    // once, a report the panicking code holds is skipped rather than waited for
+    pub fn write(&self)
+    {
+        if let Some(profiler) = &self.profiler
+        {
+            match profiler.try_lock()
+            {
+                Ok(mut profiler) => profiler.write(),
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().write(),
+                Err(TryLockError::WouldBlock) => eprintln!("Cannot write profile: busy"),
+            }
+        }
+        if let Some(coverage) = &self.coverage
+        {
+            match coverage.try_lock()
+            {
+                Ok(mut coverage) => coverage.write(),
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().write(),
+                Err(TryLockError::WouldBlock) => eprintln!("Cannot write coverage: busy"),
+            }
+        }
+    }
+}
+
+impl Computer // reports
+{
+    pub(crate) fn make_reports(profiler: Option<Profiler>, coverage: Option<Coverage>) -> Reports
+    {
+        Reports
+        {
+            profiler: profiler.map(|profiler| Arc::new(Mutex::new(profiler))),
+            coverage: coverage.map(|coverage| Arc::new(Mutex::new(coverage))),
+        }
+    }
+
+    pub fn reports(&self) -> Reports
+    {
+        self.reports.clone()
+    }
+
+    pub(crate) fn reporting(&self) -> bool
+    {
+        self.reports.profiler.is_some() || self.reports.coverage.is_some()
+    }
+
+    pub(crate) fn report_instruction(&mut self, fetch: (u32, u32), transfer_type: TransferType,
+                                     interrupted: Option<u32>)
+    {
+        if let Some(profiler) = &self.reports.profiler
+        {
+            let mut profiler = profiler.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            profiler.retire(fetch, transfer_type, interrupted);
+        }
+        if let Some(coverage) = &self.reports.coverage
+        {
+            let mut coverage = coverage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            coverage.retire(fetch, interrupted);
+        }
+    }
+}
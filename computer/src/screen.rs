@@ -0,0 +1,46 @@
+use crate::framebuffer::Framebuffer;
+use crate::text_mode::{TextDisplay, TextScreen};
+use crate::video_layers::VideoLayers;
+
+// everything the display shows, back to front
+pub struct Screen<'a>
+{
+    pub framebuffer: Framebuffer<'a>,
+    pub layers: Option<VideoLayers<'a>>,
+    pub text: Option<TextScreen<'a>>,
+}
+
+impl<'a> Screen<'a>
+{
+    pub fn width(&self) -> u32
+    {
+        self.framebuffer.width
+    }
+
+    pub fn height(&self) -> u32
+    {
+        self.framebuffer.height
+    }
+
+    pub fn row_to_rgba(&self, y: u32, out: &mut [u8])
+    {
+        if let Some(text) = &self.text
+        {
+            if text.display == TextDisplay::Replace
+            {
+                text.draw_row(y, out);
+                return;
+            }
+        }
+
+        self.framebuffer.row_to_rgba(y, out);
+        if let Some(layers) = &self.layers
+        {
+            layers.draw_row(y, out);
+        }
+        if let Some(text) = &self.text
+        {
+            text.draw_row(y, out);
+        }
+    }
+}
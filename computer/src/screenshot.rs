@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::Computer;
+use crate::screen::Screen;
+
+impl Computer // screenshots
+{
+    // saves the picture as shown on the display, layers and text included, as a binary PPM
+    pub fn save_screenshot(&self, path: &Path) -> io::Result<()>
+    {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.screen().write_ppm(&mut file)?;
+        file.flush()
+    }
+
+    // set by the guest writing a non-zero byte to the screenshot request register, cleared here
+    pub fn take_screenshot_request(&mut self) -> bool
+    {
+        let address = self.memory.display_screenshot_address() as usize;
+        if self.memory.read_byte(address) == 0
+        {
+            return false;
+        }
+        self.memory.write_byte(address, 0);
+        true
+    }
+}
+
+impl Screen<'_>
+{
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()>
+    {
+        let (width, height) = (self.width(), self.height());
+        writeln!(out, "P6\n{width} {height}\n255")?;
+
+        let mut rgba = vec![0u8; width as usize * 4];
+        let mut rgb = Vec::with_capacity(width as usize * 3);
+        for y in 0..height
+        {
+            self.row_to_rgba(y, &mut rgba);
+            rgb.clear();
+            for pixel in rgba.chunks_exact(4)
+            {
+                rgb.extend_from_slice(&pixel[..3]);
+            }
+            out.write_all(&rgb)?;
+        }
+
+        Ok(())
+    }
+}
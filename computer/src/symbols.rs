@@ -0,0 +1,394 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::dwarf::{parse_line_table, LineRow};
+
+const SHT_SYMTAB: u32 = 2;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+type Entry = (u32, String); // address, name
+
+// guest symbols for naming addresses in diagnostics, and source lines when an ELF file has them; shared cheaply
+#[derive(Clone, Default)]
+pub struct Symbols
+{
+    entries: Arc<Vec<Entry>>, // sorted by address
+    lines: Arc<Vec<LineRow>>, // sorted by address
+}
+
+fn invalid(message: String) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// field readers for either byte order
+struct Reader<'a>
+{
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_>
+{
+    fn u8(&self, offset: usize) -> Option<u8>
+    {
+        self.bytes.get(offset).copied()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16>
+    {
+        let bytes = self.bytes.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32>
+    {
+        let bytes = self.bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn string(&self, offset: usize) -> Option<&str>
+    {
+        let bytes = self.bytes.get(offset..)?;
+        let end = bytes.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&bytes[..end]).ok()
+    }
+}
+
+impl Symbols
+{
+    // an ELF file with a .symtab (and .debug_line) or a map file of "address name" lines (hex addresses, nm output
+    // works too)
+    pub fn load(path: &Path) -> io::Result<Symbols>
+    {
+        let bytes = fs::read(path)?;
+        let (entries, lines) = if bytes.starts_with(b"\x7FELF")
+        {
+            Self::parse_elf(&bytes).ok_or_else(|| invalid("bad ELF symbol table or line table".to_string()))?
+        }
+        else
+        {
+            let text = String::from_utf8(bytes).map_err(|_| invalid("not an ELF file or a symbol map".to_string()))?;
+            (Self::parse_map(&text).map_err(invalid)?, Vec::new())
+        };
+        Ok(Self::from_entries(entries, lines))
+    }
+
+    // all symbols of several files together
+    pub fn merge(&self, other: &Symbols) -> Symbols
+    {
+        let mut entries = self.entries.as_ref().clone();
+        entries.extend(other.entries.iter().cloned());
+        let mut lines = self.lines.as_ref().clone();
+        lines.extend(other.lines.iter().cloned());
+        Self::from_entries(entries, lines)
+    }
+
+    fn from_entries(mut entries: Vec<Entry>, mut lines: Vec<LineRow>) -> Symbols
+    {
+        entries.sort();
+        entries.dedup_by(|a, b| a.0 == b.0); // one name per address, the first alphabetically
+        lines.sort_by_key(|&(address, line, _)| (address, line)); // a sequence end gives way to a row at its address
+        Symbols { entries: Arc::new(entries), lines: Arc::new(lines) }
+    }
+
+    fn parse_map(text: &str) -> Result<Vec<Entry>, String>
+    {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (address, name) = match fields.as_slice()
+            {
+                [address, name] | [address, _, name] => (*address, *name),
+                _ => return Err(format!("bad symbol map line {}", i + 1)),
+            };
+            let address = address.strip_prefix("0x").or_else(|| address.strip_prefix("0X")).unwrap_or(address);
+            match u32::from_str_radix(address, 16)
+            {
+                Ok(address) => entries.push((address, name.to_string())),
+                Err(_) => return Err(format!("bad address on symbol map line {}", i + 1)),
+            }
+        }
+        Ok(entries)
+    }
+
+    // 32-bit ELF of either byte order; defined symbols except sections and files, and the line table if there is one
+    fn parse_elf(bytes: &[u8]) -> Option<(Vec<Entry>, Vec<LineRow>)>
+    {
+        if bytes.get(4) != Some(&1)
+        {
+            return None; // not 32 bit
+        }
+        let big_endian = bytes.get(5) == Some(&2);
+        let elf = Reader { bytes, big_endian };
+
+        let section_headers = elf.u32(32)? as usize;
+        let header_size = elf.u16(46)? as usize;
+        let count = elf.u16(48)? as usize;
+        let section = |index: usize| section_headers + index * header_size;
+        let section_names = elf.u32(section(elf.u16(50)? as usize) + 16)? as usize;
+        let contents = |name: &str| -> Option<&[u8]>
+        {
+            let name_of =
+                |header: usize| elf.u32(header).and_then(|offset| elf.string(section_names + offset as usize));
+            let header = (0..count).map(section).find(|&header| name_of(header) == Some(name))?;
+            let (offset, size) = (elf.u32(header + 16)? as usize, elf.u32(header + 20)? as usize);
+            bytes.get(offset..offset.checked_add(size)?)
+        };
+
+        let mut entries = Vec::new();
+        for index in 0..count
+        {
+            let header = section(index);
+            if elf.u32(header + 4)? != SHT_SYMTAB
+            {
+                continue;
+            }
+            let (offset, size, link, entry_size) = (elf.u32(header + 16)? as usize, elf.u32(header + 20)? as usize,
+                                                    elf.u32(header + 24)? as usize, elf.u32(header + 36)? as usize);
+            let strings = elf.u32(section(link) + 16)? as usize;
+            if entry_size < 16
+            {
+                return None;
+            }
+
+            for symbol in (offset..offset + size).step_by(entry_size).skip(1)
+            {
+                let name = elf.string(strings + elf.u32(symbol)? as usize)?;
+                let value = elf.u32(symbol + 4)?;
+                let kind = elf.u8(symbol + 12)? & 0xF;
+                let section_index = elf.u16(symbol + 14)?;
+                if name.is_empty() || section_index == 0 || kind == STT_SECTION || kind == STT_FILE
+                {
+                    continue;
+                }
+                entries.push((value, name.to_string()));
+            }
+        }
+
+        let lines = match contents(".debug_line")
+        {
+            Some(debug_line) => parse_line_table(debug_line, contents(".debug_str").unwrap_or(&[]),
+                                                 contents(".debug_line_str").unwrap_or(&[]), big_endian)?,
+            None => Vec::new(),
+        };
+        Some((entries, lines))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_map(text: &str) -> Symbols
+    {
+        Self::from_entries(Self::parse_map(text).unwrap(), Vec::new())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_lines(&self, lines: Vec<LineRow>) -> Symbols
+    {
+        Self::from_entries(self.entries.as_ref().clone(), lines)
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    // address and name, in address order
+    pub(crate) fn entries(&self) -> &[Entry]
+    {
+        &self.entries
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u32>
+    {
+        self.entries.iter().find(|(_, symbol)| symbol == name).map(|(address, _)| *address)
+    }
+
+    // nearest symbol at or below the address, and the offset from it
+    pub fn lookup(&self, address: u32) -> Option<(&str, u32)>
+    {
+        let index = self.entries.partition_point(|(symbol, _)| *symbol <= address).checked_sub(1)?;
+        let (symbol, name) = &self.entries[index];
+        Some((name, address - symbol))
+    }
+
+    // name exactly at the address
+    pub fn name_at(&self, address: u32) -> Option<&str>
+    {
+        match self.lookup(address)
+        {
+            Some((name, 0)) => Some(name),
+            _ => None,
+        }
+    }
+
+    // "name+0x1c", or none below the first symbol
+    pub fn location(&self, address: u32) -> Option<String>
+    {
+        self.lookup(address).map(|(name, offset)| match offset
+        {
+            0 => name.to_string(),
+            offset => format!("{name}+{offset:#x}"),
+        })
+    }
+
+    pub fn has_lines(&self) -> bool
+    {
+        !self.lines.is_empty()
+    }
+
+    // source file and line of the instruction at the address
+    pub fn line(&self, address: u32) -> Option<(&str, u32)>
+    {
+        let index = self.lines.partition_point(|&(row, _, _)| row <= address).checked_sub(1)?;
+        match &self.lines[index]
+        {
+            (_, 0, _) => None, // past the end of a sequence
+            (_, line, file) => Some((file, *line)),
+        }
+    }
+
+    // address ranges with their file and line, in address order
+    pub(crate) fn line_ranges(&self) -> impl Iterator<Item = (u32, u32, &str, u32)>
+    {
+        self.lines.windows(2)
+            .filter(|rows| rows[0].1 != 0)
+            .map(|rows| (rows[0].0, rows[1].0, rows[0].2.as_ref(), rows[0].1))
+    }
+
+    // the address, followed by <name+0x1c> when there is a symbol for it
+    pub fn describe(&self, address: u32) -> String
+    {
+        match self.location(address)
+        {
+            Some(location) => format!("{address:#010x} <{location}>"),
+            None => format!("{address:#010x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // a relocatable-style ELF with only the sections the loader looks at: names, .symtab and its .strtab
+    fn elf(big_endian: bool, symbols: &[(&str, u32, u8, u16)]) -> Vec<u8> // name, value, type, section
+    {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+        let section_names = b"\0.symtab\0.strtab\0.shstrtab\0".to_vec();
+        let mut strings = vec![0];
+        let mut table = vec![0; 16]; // the null symbol
+        for &(name, value, kind, section) in symbols
+        {
+            table.extend(u32_bytes(strings.len() as u32));
+            strings.extend(name.bytes().chain([0]));
+            table.extend(u32_bytes(value));
+            table.extend(u32_bytes(0));
+            table.extend([0x10 | kind, 0]); // global
+            table.extend(u16_bytes(section));
+        }
+
+        let mut bytes = vec![0; 52];
+        bytes[..6].copy_from_slice(&[0x7F, b'E', b'L', b'F', 1, if big_endian { 2 } else { 1 }]);
+        let mut offsets = Vec::new();
+        for contents in [&section_names, &strings, &table]
+        {
+            offsets.push(bytes.len() as u32);
+            bytes.extend(contents);
+        }
+        let headers = bytes.len() as u32;
+        bytes[32..36].copy_from_slice(&u32_bytes(headers));
+        bytes[46..48].copy_from_slice(&u16_bytes(40));
+        bytes[48..50].copy_from_slice(&u16_bytes(4));
+        bytes[50..52].copy_from_slice(&u16_bytes(1));
+
+        // null, .shstrtab, .strtab, .symtab linked to .strtab
+        bytes.extend([0; 40]);
+        for (name, kind, offset, size, link, entry_size) in [
+            (17, 3, offsets[0], section_names.len(), 0, 0),
+            (9, 3, offsets[1], strings.len(), 0, 0),
+            (1, SHT_SYMTAB, offsets[2], table.len(), 2, 16),
+        ]
+        {
+            for field in [name, kind, 0, 0, offset, size as u32, link, 0, 4, entry_size]
+            {
+                bytes.extend(u32_bytes(field));
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn map_files_name_addresses()
+    {
+        let map = "# nm output works too\n0x100 main\n00000200 T loop\n\n180 helper\n180 alias\n";
+        let symbols = Symbols::from_map(map);
+        assert_eq!(symbols.entries(), [(0x100, "main".to_string()), (0x180, "alias".to_string()),
+                                       (0x200, "loop".to_string())]);
+        assert_eq!(symbols.lookup(0x104), Some(("main", 4)));
+        assert_eq!(symbols.lookup(0xFC), None);
+        assert_eq!(symbols.name_at(0x180), Some("alias"));
+        assert_eq!(symbols.name_at(0x184), None);
+        assert_eq!(symbols.location(0x17C).as_deref(), Some("main+0x7c"));
+        assert_eq!(symbols.location(0x2000).as_deref(), Some("loop+0x1e00"));
+        assert_eq!(symbols.describe(0x200), "0x00000200 <loop>");
+        assert_eq!(symbols.describe(0x10), "0x00000010");
+        assert_eq!(symbols.address_of("loop"), Some(0x200));
+        assert_eq!(symbols.address_of("helper"), None); // gave way to alias at the same address
+
+        assert_eq!(Symbols::parse_map("0x100 main\nmain\n"), Err("bad symbol map line 2".to_string()));
+        assert_eq!(Symbols::parse_map("main 0x100\n"), Err("bad address on symbol map line 1".to_string()));
+    }
+
+    #[test]
+    fn elf_symbol_tables_load_in_either_byte_order()
+    {
+        const FUNC: u8 = 2;
+        let table = [("start", 0x1000, FUNC, 1), ("data", 0x2000, 1, 2), ("text", 0x1000, STT_SECTION, 1),
+                     ("start.s", 0, STT_FILE, 0xFFF1), ("extern", 0, FUNC, 0), ("", 0x1004, FUNC, 1)];
+        for big_endian in [true, false]
+        {
+            let (entries, lines) = Symbols::parse_elf(&elf(big_endian, &table)).unwrap();
+            assert_eq!(entries, [(0x1000, "start".to_string()), (0x2000, "data".to_string())]);
+            assert!(lines.is_empty());
+        }
+
+        let mut bytes = elf(true, &table);
+        bytes[4] = 2; // 64 bit
+        assert!(Symbols::parse_elf(&bytes).is_none());
+    }
+
+    #[test]
+    fn files_load_by_their_contents()
+    {
+        let directory = std::env::temp_dir();
+        let path = |name: &str| directory.join(format!("jupiter-symbols-{}-{name}", std::process::id()));
+        std::fs::write(path("elf"), elf(true, &[("start", 0x40, 2, 1)])).unwrap();
+        std::fs::write(path("map"), "80 loop\n").unwrap();
+        std::fs::write(path("binary"), [0xFF, 0xFE]).unwrap();
+
+        let symbols = Symbols::load(&path("elf")).unwrap().merge(&Symbols::load(&path("map")).unwrap());
+        assert_eq!(symbols.location(0x44).as_deref(), Some("start+0x4"));
+        assert_eq!(symbols.location(0x84).as_deref(), Some("loop+0x4"));
+        assert!(!symbols.has_lines());
+        let error = Symbols::load(&path("binary")).err().unwrap();
+        assert_eq!(error.to_string(), "not an ELF file or a symbol map");
+        assert!(Symbols::load(&path("missing")).is_err());
+
+        for name in ["elf", "map", "binary"]
+        {
+            let _ = std::fs::remove_file(path(name));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+
+pub(crate) const CHAR_QUEUE_CAPACITY: usize = 256;
+
+pub(crate) struct TextInput
+{
+    chars: VecDeque<char>,
+}
+
+impl TextInput
+{
+    pub(crate) fn new() -> Self
+    {
+        Self
+        {
+            chars: VecDeque::with_capacity(CHAR_QUEUE_CAPACITY),
+        }
+    }
+
+    pub(crate) fn push_char(&mut self, c: char)
+    {
+        if self.chars.len() >= CHAR_QUEUE_CAPACITY
+        {
+            return;
+        }
+        self.chars.push_back(c);
+    }
+
+    pub(crate) fn pop_char(&mut self) -> Option<char>
+    {
+        self.chars.pop_front()
+    }
+
+    pub(crate) fn available(&self) -> usize
+    {
+        self.chars.len()
+    }
+}
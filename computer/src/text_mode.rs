@@ -0,0 +1,105 @@
+// 8x16 font ROM, ISO-8859-1 code page. Glyphs are the public domain X11 misc-fixed 8x13
+// font padded to 16 rows, one byte per row, most significant bit is the leftmost pixel.
+static FONT: &[u8; 256 * 16] = include_bytes!("font8x16.bin");
+
+pub(crate) const CHAR_WIDTH: u32 = 8;
+pub(crate) const CHAR_HEIGHT: u32 = 16;
+pub(crate) const TEXT_REGISTERS_SIZE: u32 = 4; // select, cursor x, cursor y, cursor visible
+
+// CGA colours for the 4-bit foreground and background of the attribute byte
+const COLORS: [[u8; 3]; 16] =
+[
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0xAA], [0x00, 0xAA, 0x00], [0x00, 0xAA, 0xAA],
+    [0xAA, 0x00, 0x00], [0xAA, 0x00, 0xAA], [0xAA, 0x55, 0x00], [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55], [0x55, 0x55, 0xFF], [0x55, 0xFF, 0x55], [0x55, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55], [0xFF, 0x55, 0xFF], [0xFF, 0xFF, 0x55], [0xFF, 0xFF, 0xFF],
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextDisplay
+{
+    Off = 0,
+    Replace = 1, // text screen instead of the framebuffer
+    Overlay = 2, // text over the framebuffer, background colour 0 is transparent
+}
+
+impl TextDisplay
+{
+    pub(crate) fn from_select(select: u8) -> Option<TextDisplay>
+    {
+        match select
+        {
+            0 => Some(TextDisplay::Off),
+            1 => Some(TextDisplay::Replace),
+            2 => Some(TextDisplay::Overlay),
+            _ => None,
+        }
+    }
+}
+
+// borrowed view of the character buffer, cells are (character, attribute) byte pairs
+pub struct TextScreen<'a>
+{
+    pub cells: &'a [u8],
+    pub columns: u32,
+    pub rows: u32,
+    pub display: TextDisplay,
+    pub cursor: Option<(u32, u32)>,
+}
+
+impl<'a> TextScreen<'a>
+{
+    // draws pixel row y of the text screen over an RGBA row
+    pub fn draw_row(&self, y: u32, out: &mut [u8])
+    {
+        if self.display == TextDisplay::Off
+        {
+            return;
+        }
+
+        let row = y / CHAR_HEIGHT;
+        let glyph_line = y % CHAR_HEIGHT;
+
+        for (x, pixel) in out.chunks_exact_mut(4).enumerate()
+        {
+            let column = x as u32 / CHAR_WIDTH;
+            if row >= self.rows || column >= self.columns
+            {
+                if self.display == TextDisplay::Replace
+                {
+                    pixel.copy_from_slice(&[0, 0, 0, 255]);
+                }
+                continue;
+            }
+
+            let cell = 2 * (row * self.columns + column) as usize;
+            let (character, attribute) = (self.cells[cell], self.cells[cell + 1]);
+            let foreground = (attribute & 0x0F) as usize;
+            let background = (attribute >> 4) as usize;
+
+            let bits = FONT[character as usize * CHAR_HEIGHT as usize + glyph_line as usize];
+            let mut lit = bits & (0x80 >> (x as u32 % CHAR_WIDTH)) != 0;
+
+            // underline cursor on the two bottom lines of the cell
+            if self.cursor == Some((column, row)) && glyph_line >= CHAR_HEIGHT - 2
+            {
+                lit = true;
+            }
+
+            let color = if lit
+            {
+                COLORS[foreground]
+            }
+            else if background == 0 && self.display == TextDisplay::Overlay
+            {
+                continue;
+            }
+            else
+            {
+                COLORS[background]
+            };
+
+            pixel.copy_from_slice(&[color[0], color[1], color[2], 255]);
+        }
+    }
+}
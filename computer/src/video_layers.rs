@@ -0,0 +1,232 @@
+// Tile map layer and hardware sprites, composited over the framebuffer.
+// Pixel data is 8-bit indexed through the display palette, colour 0 is transparent.
+
+pub(crate) const TILE_LAYER_SIZE: u32 = 20;
+pub(crate) const SPRITE_COUNT: u32 = 64;
+pub(crate) const SPRITE_SIZE: u32 = 12;
+pub(crate) const VIDEO_LAYERS_SIZE: u32 = TILE_LAYER_SIZE + SPRITE_COUNT * SPRITE_SIZE;
+
+// tile layer registers
+const TILE_ENABLE: usize = 0;
+const TILE_SIZE_LOG2: usize = 1;
+const TILE_MAP_WIDTH: usize = 2;
+const TILE_MAP_HEIGHT: usize = 4;
+const TILE_SCROLL_X: usize = 6;
+const TILE_SCROLL_Y: usize = 8;
+const TILE_MAP_OFFSET: usize = 12;
+const TILE_DATA_OFFSET: usize = 16;
+
+// sprite entry
+const SPRITE_X: usize = 0;
+const SPRITE_Y: usize = 2;
+const SPRITE_WIDTH: usize = 4;
+const SPRITE_HEIGHT: usize = 5;
+const SPRITE_FLAGS: usize = 6;
+const SPRITE_DATA_OFFSET: usize = 8;
+
+const SPRITE_ENABLE: u8 = 1 << 0;
+const SPRITE_FLIP_X: u8 = 1 << 1;
+const SPRITE_FLIP_Y: u8 = 1 << 2;
+const SPRITE_BEHIND: u8 = 1 << 3; // drawn under the tile layer
+
+fn half(bytes: &[u8], at: usize) -> u16
+{
+    ((bytes[at] as u16) << 8) | bytes[at + 1] as u16
+}
+
+fn word(bytes: &[u8], at: usize) -> u32
+{
+    ((bytes[at] as u32) << 24) | ((bytes[at + 1] as u32) << 16) | ((bytes[at + 2] as u32) << 8) | bytes[at + 3] as u32
+}
+
+// borrowed view of the layer registers, offsets are relative to the start of VRAM
+pub struct VideoLayers<'a>
+{
+    pub registers: &'a [u8],
+    pub vram: &'a [u8],
+    pub palette: &'a [u8],
+}
+
+impl<'a> VideoLayers<'a>
+{
+    pub fn enabled(&self) -> bool
+    {
+        self.registers[TILE_ENABLE] != 0 || (0..SPRITE_COUNT).any(|i| self.sprite(i)[SPRITE_FLAGS] & SPRITE_ENABLE != 0)
+    }
+
+    fn sprite(&self, index: u32) -> &'a [u8]
+    {
+        let start = (TILE_LAYER_SIZE + index * SPRITE_SIZE) as usize;
+        &self.registers[start..start + SPRITE_SIZE as usize]
+    }
+
+    fn plot(&self, pixel: &mut [u8], color: u8)
+    {
+        if color == 0
+        {
+            return;
+        }
+        let entry = 3 * color as usize;
+        pixel.copy_from_slice(&[self.palette[entry], self.palette[entry + 1], self.palette[entry + 2], 255]);
+    }
+
+    // VRAM byte, 0 (transparent) outside VRAM; offsets come from the guest and wrap rather than overflow
+    fn vram_byte(&self, offset: u32) -> u8
+    {
+        self.vram.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn draw_sprites(&self, y: u32, out: &mut [u8], behind: bool)
+    {
+        // lower numbered sprites end up on top
+        for index in (0..SPRITE_COUNT).rev()
+        {
+            let sprite = self.sprite(index);
+            let flags = sprite[SPRITE_FLAGS];
+            if flags & SPRITE_ENABLE == 0 || (flags & SPRITE_BEHIND != 0) != behind
+            {
+                continue;
+            }
+
+            let sprite_x = half(sprite, SPRITE_X) as i16 as i32;
+            let sprite_y = half(sprite, SPRITE_Y) as i16 as i32;
+            let width = sprite[SPRITE_WIDTH] as i32;
+            let height = sprite[SPRITE_HEIGHT] as i32;
+
+            let mut line = y as i32 - sprite_y;
+            if line < 0 || line >= height
+            {
+                continue;
+            }
+            if flags & SPRITE_FLIP_Y != 0
+            {
+                line = height - 1 - line;
+            }
+
+            let data = word(sprite, SPRITE_DATA_OFFSET).wrapping_add((line * width) as u32);
+            for column in 0..width
+            {
+                let x = sprite_x + column;
+                if x < 0 || (x as usize) >= out.len() / 4
+                {
+                    continue;
+                }
+                let source = if flags & SPRITE_FLIP_X != 0 { width - 1 - column } else { column };
+                let color = self.vram_byte(data.wrapping_add(source as u32));
+                self.plot(&mut out[4 * x as usize..4 * x as usize + 4], color);
+            }
+        }
+    }
+
+    fn draw_tiles(&self, y: u32, out: &mut [u8])
+    {
+        let registers = self.registers;
+        if registers[TILE_ENABLE] == 0
+        {
+            return;
+        }
+
+        let tile_size = 1u32 << registers[TILE_SIZE_LOG2].min(6);
+        let map_width = half(registers, TILE_MAP_WIDTH) as u32;
+        let map_height = half(registers, TILE_MAP_HEIGHT) as u32;
+        if map_width == 0 || map_height == 0
+        {
+            return;
+        }
+
+        let map_offset = word(registers, TILE_MAP_OFFSET);
+        let data_offset = word(registers, TILE_DATA_OFFSET);
+
+        // the map wraps around in both directions
+        let map_y = (y + half(registers, TILE_SCROLL_Y) as u32) % (map_height * tile_size);
+        let scroll_x = half(registers, TILE_SCROLL_X) as u32;
+
+        for (x, pixel) in out.chunks_exact_mut(4).enumerate()
+        {
+            let map_x = (x as u32 + scroll_x) % (map_width * tile_size);
+            let index = (map_y / tile_size).wrapping_mul(map_width).wrapping_add(map_x / tile_size);
+            let entry = map_offset.wrapping_add(index.wrapping_mul(2));
+            let tile = ((self.vram_byte(entry) as u32) << 8) | self.vram_byte(entry.wrapping_add(1)) as u32;
+
+            let pixel_offset = (map_y % tile_size) * tile_size + map_x % tile_size;
+            let tile_start = data_offset.wrapping_add(tile * tile_size * tile_size);
+            let color = self.vram_byte(tile_start.wrapping_add(pixel_offset));
+            self.plot(pixel, color);
+        }
+    }
+
+    // draws pixel row y of all layers over an RGBA row
+    pub fn draw_row(&self, y: u32, out: &mut [u8])
+    {
+        self.draw_sprites(y, out, true);
+        self.draw_tiles(y, out);
+        self.draw_sprites(y, out, false);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn put_word(bytes: &mut [u8], at: usize, value: u32)
+    {
+        bytes[at..at + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn extreme_offsets_wrap_around()
+    {
+        let mut palette = vec![0; 768];
+        palette[3..6].copy_from_slice(&[10, 20, 30]);
+        let mut vram = vec![0; 64];
+        vram[0] = 1;
+        vram[1] = 1;
+
+        // a 2x1 sprite whose data starts at the last offset, its second pixel is the first VRAM byte
+        let mut registers = vec![0; VIDEO_LAYERS_SIZE as usize];
+        let sprite = TILE_LAYER_SIZE as usize;
+        registers[sprite + SPRITE_WIDTH] = 2;
+        registers[sprite + SPRITE_HEIGHT] = 1;
+        registers[sprite + SPRITE_FLAGS] = SPRITE_ENABLE;
+        put_word(&mut registers, sprite + SPRITE_DATA_OFFSET, u32::MAX);
+        let layers = VideoLayers { registers: &registers, vram: &vram, palette: &palette };
+        let mut row = vec![0; 4 * 4];
+        layers.draw_row(0, &mut row);
+        assert_eq!(row[..8], [0, 0, 0, 0, 10, 20, 30, 255]);
+
+        // a one tile map at the last offset reads tile 1 across the wrap, which puts its data at the first VRAM byte
+        let mut registers = vec![0; VIDEO_LAYERS_SIZE as usize];
+        registers[TILE_ENABLE] = 1;
+        registers[TILE_MAP_WIDTH + 1] = 1;
+        registers[TILE_MAP_HEIGHT + 1] = 1;
+        put_word(&mut registers, TILE_MAP_OFFSET, u32::MAX);
+        put_word(&mut registers, TILE_DATA_OFFSET, u32::MAX);
+        let layers = VideoLayers { registers: &registers, vram: &vram, palette: &palette };
+        let mut row = vec![0; 4 * 4];
+        layers.draw_row(0, &mut row);
+        assert_eq!(row[..4], [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn largest_map_entries_wrap_around()
+    {
+        let mut palette = vec![0; 768];
+        palette[3..6].copy_from_slice(&[10, 20, 30]);
+        let vram = vec![1, 1, 1, 0];
+
+        // the last entry of a 65535x65535 map is 2 * (65535 * 65535 - 1) bytes in, which wraps to 0xFFFC_0000
+        let mut registers = vec![0; VIDEO_LAYERS_SIZE as usize];
+        registers[TILE_ENABLE] = 1;
+        registers[TILE_MAP_WIDTH..TILE_MAP_WIDTH + 2].copy_from_slice(&[0xFF, 0xFF]);
+        registers[TILE_MAP_HEIGHT..TILE_MAP_HEIGHT + 2].copy_from_slice(&[0xFF, 0xFF]);
+        registers[TILE_SCROLL_X..TILE_SCROLL_X + 2].copy_from_slice(&[0xFF, 0xFE]);
+        registers[TILE_SCROLL_Y..TILE_SCROLL_Y + 2].copy_from_slice(&[0xFF, 0xFE]);
+        put_word(&mut registers, TILE_MAP_OFFSET, 0u32.wrapping_sub(0xFFFC_0000));
+        put_word(&mut registers, TILE_DATA_OFFSET, 2u32.wrapping_sub(0x0101)); // tile 0x0101 is the third byte
+        let layers = VideoLayers { registers: &registers, vram: &vram, palette: &palette };
+        let mut row = vec![0; 4 * 4];
+        layers.draw_row(0, &mut row);
+        assert_eq!(row[..4], [10, 20, 30, 255]);
+    }
+}
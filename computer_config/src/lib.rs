@@ -1,6 +1,86 @@
 use std::fs::File;
 use std::io::Read;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum PixelFormat
+{
+    Rgb888 = 0,
+    Indexed8 = 1,
+    Rgb565 = 2,
+    Xrgb8888 = 3,
+}
+
+impl PixelFormat
+{
+    pub const COUNT: usize = 4;
+
+    pub fn from_mode(mode: u8) -> Option<PixelFormat>
+    {
+        match mode
+        {
+            0 => Some(PixelFormat::Rgb888),
+            1 => Some(PixelFormat::Indexed8),
+            2 => Some(PixelFormat::Rgb565),
+            3 => Some(PixelFormat::Xrgb8888),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32
+    {
+        match self
+        {
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Indexed8 => 1,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Xrgb8888 => 4,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<PixelFormat>
+    {
+        match name.to_lowercase().as_str()
+        {
+            "rgb888" => Some(PixelFormat::Rgb888),
+            "indexed8" => Some(PixelFormat::Indexed8),
+            "rgb565" => Some(PixelFormat::Rgb565),
+            "xrgb8888" => Some(PixelFormat::Xrgb8888),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchKind
+{
+    Read,
+    Write,
+    Access, // read or write
+    Execute,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchAction
+{
+    Halt,
+    Log, // report the access on stderr and carry on
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scaling
+{
+    Integer, // largest whole multiple that fits
+    Fit, // fill the window keeping the aspect ratio
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter
+{
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone)]
 pub struct Config
 {
     rom_filename: Option<String>,
@@ -12,6 +92,42 @@ pub struct Config
 
     width: u32,
     height: u32,
+    pixel_format: PixelFormat,
+    text_mode: Option<(u32, u32)>, // columns, rows
+    double_buffer: bool,
+    sample_rate: u32,
+    audio_channels: u32,
+    audio_buffer_size: u32, // samples
+
+    // frontend
+    cycles_per_frame: u32,
+    frame_rate: u32,
+    clock_frequency: Option<u64>,
+    scale: u32,
+    scaling: Scaling,
+    filter: Filter,
+    fullscreen: bool,
+    dump_dir: String,
+    record: Option<String>,
+    audio_out: Option<String>,
+    audio_player: Option<String>,
+    monitor: bool,
+    gdb_port: Option<u16>,
+    watchpoints: Vec<(u32, u32, WatchKind, WatchAction)>, // address, length
+    trace_buffer: u32, // bus transfers kept for dumping, 0 for none
+    trace: Option<String>, // instruction trace, text
+    trace_binary: Option<String>, // instruction trace, binary records
+    trace_pc: Option<(u32, u32)>, // address, length
+    trace_cycles: Option<(u64, u64)>, // first, last
+    symbols: Vec<String>, // ELF or map files naming guest addresses
+    profile: Option<String>, // per function and per instruction counts
+    profile_folded: Option<String>, // call stacks for flamegraph tools
+    coverage: Option<String>, // lcov report of executed instructions and branch directions
+
+    // headless
+    headless: bool,
+    max_cycles: Option<u64>,
+    dump_every: Option<u64>,
 }
 
 impl Config
@@ -40,9 +156,100 @@ impl Config
         return Some(value * suffix);
     }
 
+    fn parse_frequency(input: &str) -> Option<u64>
+    {
+        if let Ok(value) = input.parse::<u64>()
+        {
+            return Some(value);
+        }
+
+        // the suffix may be any character, empty values have none
+        let (number, suffix) = input.split_at(input.char_indices().last()?.0);
+        let value = number.parse::<f64>().ok()?;
+        let suffix = match suffix
+        {
+            "k" | "K" => 1e3,
+            "m" | "M" => 1e6,
+            "g" | "G" => 1e9,
+            _ => return None,
+        };
+
+        Some((value * suffix) as u64)
+    }
+
+    fn parse_address(input: &str) -> Option<u32>
+    {
+        match input.strip_prefix("0x").or_else(|| input.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => input.parse().ok(),
+        }
+    }
+
+    // ADDRESS[+LENGTH]
+    fn parse_range(input: &str) -> Option<(u32, u32)>
+    {
+        match input.split_once('+')
+        {
+            Some((address, len)) => Some((Self::parse_address(address)?, Self::parse_address(len)?)),
+            None => Some((Self::parse_address(input)?, 1)),
+        }
+    }
+
+    // FIRST-[LAST], both inclusive
+    fn parse_cycle_window(input: &str) -> Option<(u64, u64)>
+    {
+        let (first, last) = input.split_once('-')?;
+        let first = first.parse().ok()?;
+        let last = if last.is_empty() { u64::MAX } else { last.parse().ok()? };
+        if first > last
+        {
+            return None;
+        }
+        Some((first, last))
+    }
+
+    // ADDRESS[+LENGTH]:r|w|rw|x[:log]
+    fn parse_watchpoint(input: &str) -> Option<(u32, u32, WatchKind, WatchAction)>
+    {
+        let mut fields = input.split(':');
+        let (address, len) = Self::parse_range(fields.next()?)?;
+
+        let kind = match fields.next()?
+        {
+            "r" => WatchKind::Read,
+            "w" => WatchKind::Write,
+            "rw" => WatchKind::Access,
+            "x" => WatchKind::Execute,
+            _ => return None,
+        };
+        let action = match fields.next()
+        {
+            None | Some("halt") => WatchAction::Halt,
+            Some("log") => WatchAction::Log,
+            _ => return None,
+        };
+
+        if len == 0 || fields.next().is_some()
+        {
+            return None;
+        }
+        Some((address, len, kind, action))
+    }
+
+    fn parse_switch(input: &str) -> Option<bool>
+    {
+        match input
+        {
+            "on" | "true" | "1" => Some(true),
+            "off" | "false" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
     pub fn from_args(args: Vec<String>) -> Config
     {
-        if args.len() == 8
+        if args.len() >= 8
         {
             return Self::parse_args(args);
         }
@@ -112,9 +319,7 @@ impl Config
         let width = *&args[6].parse::<u32>().unwrap();
         let height = *&args[7].parse::<u32>().unwrap();
 
-        let vram_size = 3 * width * height;
-
-        Config
+        let mut config = Config
         {
             rom_filename,
             program_filename,
@@ -123,7 +328,190 @@ impl Config
             memory_size,
             width,
             height,
-            vram_size
+            vram_size: 0, // sized for the pixel format unless given explicitly
+            pixel_format: PixelFormat::Rgb888,
+            text_mode: None,
+            double_buffer: false,
+            sample_rate: 22050,
+            audio_channels: 1,
+            audio_buffer_size: 4096,
+            cycles_per_frame: 10_000,
+            frame_rate: 60,
+            clock_frequency: None,
+            scale: 1,
+            scaling: Scaling::Integer,
+            filter: Filter::Nearest,
+            fullscreen: false,
+            dump_dir: String::from("."),
+            record: None,
+            audio_out: None,
+            audio_player: None,
+            monitor: false,
+            gdb_port: None,
+            watchpoints: Vec::new(),
+            trace_buffer: 0,
+            trace: None,
+            trace_binary: None,
+            trace_pc: None,
+            trace_cycles: None,
+            symbols: Vec::new(),
+            profile: None,
+            profile_folded: None,
+            coverage: None,
+            headless: false,
+            max_cycles: None,
+            dump_every: None,
+        };
+
+        config.parse_options(&args[8..]);
+
+        if config.monitor && config.gdb_port.is_some()
+        {
+            panic!("Monitor and gdb can't be used together");
+        }
+
+        if config.vram_size == 0
+        {
+            config.vram_size = config.pixel_format.bytes_per_pixel() * width * height;
+        }
+
+        config
+    }
+
+    // optional key=value settings after the positional arguments
+    fn parse_options(&mut self, options: &[String])
+    {
+        for option in options.iter().filter(|option| !option.trim().is_empty())
+        {
+            let (key, value) = match option.split_once('=')
+            {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => panic!("Bad option {option}"),
+            };
+
+            match key
+            {
+                "cycles_per_frame" => self.cycles_per_frame = value.parse().expect("Bad cycles per frame"),
+                "frame_rate" =>
+                    {
+                        self.frame_rate = value.parse().expect("Bad frame rate");
+                        if self.frame_rate == 0
+                        {
+                            panic!("Frame rate must be positive");
+                        }
+                    }
+                "clock_frequency" =>
+                    {
+                        let frequency = match Self::parse_frequency(value)
+                        {
+                            Some(0) | None => panic!("Bad clock frequency"),
+                            Some(frequency) => frequency,
+                        };
+                        self.clock_frequency = Some(frequency);
+                    }
+                "pixel_format" => match PixelFormat::from_name(value)
+                {
+                    Some(format) => self.pixel_format = format,
+                    None => panic!("Bad pixel format"),
+                },
+                "text_mode" =>
+                    {
+                        let size = value.split_once('x')
+                            .and_then(|(columns, rows)| columns.parse::<u32>().ok().zip(rows.parse::<u32>().ok()));
+                        match size
+                        {
+                            Some((columns, rows)) if (1..=255).contains(&columns) && (1..=255).contains(&rows) =>
+                                self.text_mode = Some((columns, rows)),
+                            _ => panic!("Bad text mode size"),
+                        }
+                    }
+                "double_buffer" => self.double_buffer = Self::parse_switch(value).expect("Bad double buffer setting"),
+                "sample_rate" => match value.parse::<u32>()
+                {
+                    Ok(rate) if (1000..=192_000).contains(&rate) => self.sample_rate = rate,
+                    _ => panic!("Bad sample rate"),
+                },
+                "audio_channels" => match value.parse::<u32>()
+                {
+                    Ok(channels) if (1..=2).contains(&channels) => self.audio_channels = channels,
+                    _ => panic!("Bad audio channel count"),
+                },
+                "audio_buffer" => match value.parse::<u32>()
+                {
+                    Ok(size) if (2..=1 << 20).contains(&size) && size % 2 == 0 => self.audio_buffer_size = size,
+                    _ => panic!("Bad audio buffer size"),
+                },
+                "audio_out" => self.audio_out = Some(value.to_string()),
+                "audio_player" => self.audio_player = Some(value.to_string()),
+                "scale" =>
+                    {
+                        self.scale = value.parse().expect("Bad scale");
+                        if self.scale == 0
+                        {
+                            panic!("Scale must be positive");
+                        }
+                    }
+                "scaling" => match value
+                {
+                    "integer" => self.scaling = Scaling::Integer,
+                    "fit" => self.scaling = Scaling::Fit,
+                    _ => panic!("Bad scaling"),
+                },
+                "filter" => match value
+                {
+                    "nearest" => self.filter = Filter::Nearest,
+                    "linear" => self.filter = Filter::Linear,
+                    _ => panic!("Bad filter"),
+                },
+                "fullscreen" => self.fullscreen = Self::parse_switch(value).expect("Bad fullscreen setting"),
+                "dump_dir" => self.dump_dir = value.to_string(),
+                "record" => self.record = Some(value.to_string()),
+                "monitor" => self.monitor = Self::parse_switch(value).expect("Bad monitor setting"),
+                "gdb" => match value.parse::<u16>()
+                {
+                    Ok(port) => self.gdb_port = Some(port),
+                    Err(_) => panic!("Bad gdb port"),
+                },
+                "watch" => match Self::parse_watchpoint(value)
+                {
+                    Some(watchpoint) => self.watchpoints.push(watchpoint),
+                    None => panic!("Bad watchpoint {value}"),
+                },
+                "trace_buffer" => self.trace_buffer = value.parse().expect("Bad trace buffer size"),
+                "trace" => self.trace = Some(value.to_string()),
+                "trace_binary" => self.trace_binary = Some(value.to_string()),
+                "trace_pc" => match Self::parse_range(value)
+                {
+                    Some(range) => self.trace_pc = Some(range),
+                    None => panic!("Bad trace pc range"),
+                },
+                "symbols" => self.symbols.push(value.to_string()),
+                "profile" => self.profile = Some(value.to_string()),
+                "profile_folded" => self.profile_folded = Some(value.to_string()),
+                "coverage" => self.coverage = Some(value.to_string()),
+                "trace_cycles" => match Self::parse_cycle_window(value)
+                {
+                    Some(window) => self.trace_cycles = Some(window),
+                    None => panic!("Bad trace cycle window"),
+                },
+                "headless" => self.headless = Self::parse_switch(value).expect("Bad headless setting"),
+                "max_cycles" => match value.parse::<u64>()
+                {
+                    Ok(0) | Err(_) => panic!("Bad max cycles"),
+                    Ok(cycles) => self.max_cycles = Some(cycles),
+                },
+                "dump_every" => match value.parse::<u64>()
+                {
+                    Ok(0) | Err(_) => panic!("Bad dump interval"),
+                    Ok(cycles) => self.dump_every = Some(cycles),
+                },
+                "vram_size" => match Self::parse_size(&value.to_string())
+                {
+                    Some(size) if size > 0 && size < 1 << 32 => self.vram_size = size as u32,
+                    _ => panic!("Bad vram size"),
+                },
+                _ => panic!("Unknown option {key}"),
+            }
         }
     }
 }
@@ -154,6 +542,10 @@ impl Config // getters
     {
         self.vram_size
     }
+    pub fn vram_pages(&self) -> u32
+    {
+        if self.double_buffer { 2 } else { 1 }
+    }
     pub fn width(&self) -> u32
     {
         self.width
@@ -162,4 +554,225 @@ impl Config // getters
     {
         self.height
     }
-}
\ No newline at end of file
+    pub fn pixel_format(&self) -> PixelFormat
+    {
+        self.pixel_format
+    }
+    pub fn text_mode(&self) -> Option<(u32, u32)>
+    {
+        self.text_mode
+    }
+    pub fn sample_rate(&self) -> u32
+    {
+        self.sample_rate
+    }
+    pub fn audio_channels(&self) -> u32
+    {
+        self.audio_channels
+    }
+    pub fn audio_buffer_size(&self) -> u32
+    {
+        self.audio_buffer_size
+    }
+    pub fn audio_out(&self) -> &Option<String>
+    {
+        &self.audio_out
+    }
+    pub fn audio_player(&self) -> &Option<String>
+    {
+        &self.audio_player
+    }
+    pub fn cycles_per_frame(&self) -> u32
+    {
+        self.cycles_per_frame
+    }
+    pub fn frame_rate(&self) -> u32
+    {
+        self.frame_rate
+    }
+    pub fn clock_frequency(&self) -> Option<u64>
+    {
+        self.clock_frequency
+    }
+    // emulated time base, the clock if set, otherwise one frame's worth of cycles per frame
+    pub fn cycles_per_second(&self) -> u64
+    {
+        match self.clock_frequency
+        {
+            Some(frequency) => frequency,
+            None => (self.cycles_per_frame as u64 * self.frame_rate as u64).max(1),
+        }
+    }
+    pub fn scale(&self) -> u32
+    {
+        self.scale
+    }
+    pub fn scaling(&self) -> Scaling
+    {
+        self.scaling
+    }
+    pub fn filter(&self) -> Filter
+    {
+        self.filter
+    }
+    pub fn fullscreen(&self) -> bool
+    {
+        self.fullscreen
+    }
+    pub fn dump_dir(&self) -> &String
+    {
+        &self.dump_dir
+    }
+    pub fn record(&self) -> &Option<String>
+    {
+        &self.record
+    }
+    pub fn monitor(&self) -> bool
+    {
+        self.monitor
+    }
+    pub fn gdb_port(&self) -> Option<u16>
+    {
+        self.gdb_port
+    }
+    pub fn watchpoints(&self) -> &Vec<(u32, u32, WatchKind, WatchAction)>
+    {
+        &self.watchpoints
+    }
+    pub fn trace_buffer(&self) -> u32
+    {
+        self.trace_buffer
+    }
+    pub fn trace(&self) -> &Option<String>
+    {
+        &self.trace
+    }
+    pub fn trace_binary(&self) -> &Option<String>
+    {
+        &self.trace_binary
+    }
+    pub fn trace_pc(&self) -> Option<(u32, u32)>
+    {
+        self.trace_pc
+    }
+    pub fn trace_cycles(&self) -> Option<(u64, u64)>
+    {
+        self.trace_cycles
+    }
+    pub fn symbols(&self) -> &Vec<String>
+    {
+        &self.symbols
+    }
+    pub fn profile(&self) -> &Option<String>
+    {
+        &self.profile
+    }
+    pub fn profile_folded(&self) -> &Option<String>
+    {
+        &self.profile_folded
+    }
+    pub fn coverage(&self) -> &Option<String>
+    {
+        &self.coverage
+    }
+    pub fn headless(&self) -> bool
+    {
+        self.headless
+    }
+    pub fn max_cycles(&self) -> Option<u64>
+    {
+        self.max_cycles
+    }
+    pub fn dump_every(&self) -> Option<u64>
+    {
+        self.dump_every
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn config(options: &[&str]) -> Config
+    {
+        let mut args = vec!["jupiter", "rom.bin", "none", "disk.img", "1M", "16M", "320", "200"];
+        args.extend_from_slice(options);
+        Config::from_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    // the panic message a bad config stops with
+    fn error(options: &[&str]) -> String
+    {
+        let payload = std::panic::catch_unwind(|| config(options)).err().expect("config accepted");
+        match payload.downcast::<String>()
+        {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast::<&str>().map(|message| message.to_string()).unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn positional_arguments_and_defaults()
+    {
+        let config = config(&[]);
+        assert_eq!(config.rom_filename().as_deref(), Some("rom.bin"));
+        assert_eq!(config.program_filename(), &None);
+        assert_eq!((config.disk_size(), config.memory_size()), (1 << 20, 16 << 20));
+        assert_eq!((config.width(), config.height(), config.vram_size()), (320, 200, 3 * 320 * 200));
+        assert_eq!((config.vram_pages(), config.cycles_per_frame(), config.clock_frequency()), (1, 10_000, None));
+        assert!(config.pixel_format() == PixelFormat::Rgb888 && config.scaling() == Scaling::Integer);
+        assert!(!config.headless() && config.watchpoints().is_empty() && config.symbols().is_empty());
+    }
+
+    #[test]
+    fn options_are_key_value_pairs()
+    {
+        let config = config(&["pixel_format=Indexed8", " double_buffer = on ", "", "clock_frequency=1.5M",
+                              "text_mode=80x25", "trace_pc=0x100+0x20", "trace_cycles=10-", "symbols=a.map",
+                              "symbols=b.elf", "headless=1", "max_cycles=5", "scaling=fit", "audio_buffer=512"]);
+        assert!(config.pixel_format() == PixelFormat::Indexed8);
+        assert_eq!((config.vram_pages(), config.vram_size()), (2, 320 * 200));
+        assert_eq!(config.clock_frequency(), Some(1_500_000));
+        assert_eq!(config.text_mode(), Some((80, 25)));
+        assert_eq!(config.trace_pc(), Some((0x100, 0x20)));
+        assert_eq!(config.trace_cycles(), Some((10, u64::MAX)));
+        assert_eq!(config.symbols(), &["a.map", "b.elf"]);
+        assert_eq!((config.headless(), config.max_cycles()), (true, Some(5)));
+        assert!(config.scaling() == Scaling::Fit);
+        assert_eq!(config.audio_buffer_size(), 512);
+    }
+
+    #[test]
+    fn watchpoints_take_a_range_a_kind_and_an_action()
+    {
+        let config = config(&["watch=0x10:w", "watch=32+8:rw:log", "watch=0x20:x:halt"]);
+        let watchpoints: Vec<(u32, u32, bool, bool)> = config.watchpoints().iter()
+            .map(|&(address, len, kind, action)| (address, len, kind == WatchKind::Access, action == WatchAction::Log))
+            .collect();
+        assert_eq!(watchpoints, [(0x10, 1, false, false), (32, 8, true, true), (0x20, 1, false, false)]);
+        assert!(config.watchpoints()[2].2 == WatchKind::Execute);
+    }
+
+    #[test]
+    fn bad_settings_say_which()
+    {
+        assert_eq!(error(&["headless"]), "Bad option headless");
+        assert_eq!(error(&["colour=red"]), "Unknown option colour");
+        assert_eq!(error(&["clock_frequency=0"]), "Bad clock frequency");
+        assert_eq!(error(&["clock_frequency=5x"]), "Bad clock frequency");
+        assert_eq!(error(&["clock_frequency="]), "Bad clock frequency");
+        assert_eq!(error(&["clock_frequency=5é"]), "Bad clock frequency");
+        assert_eq!(error(&["frame_rate=0"]), "Frame rate must be positive");
+        assert_eq!(error(&["pixel_format=rgb332"]), "Bad pixel format");
+        assert_eq!(error(&["text_mode=0x25"]), "Bad text mode size");
+        assert_eq!(error(&["sample_rate=500"]), "Bad sample rate");
+        assert_eq!(error(&["watch=0x10"]), "Bad watchpoint 0x10");
+        assert_eq!(error(&["watch=0x10+0:r"]), "Bad watchpoint 0x10+0:r");
+        assert_eq!(error(&["watch=0x10:r:stop"]), "Bad watchpoint 0x10:r:stop");
+        assert_eq!(error(&["trace_cycles=9-3"]), "Bad trace cycle window");
+        assert_eq!(error(&["max_cycles=0"]), "Bad max cycles");
+        assert_eq!(error(&["vram_size=8P"]), "Bad vram size");
+        assert_eq!(error(&["monitor=on", "gdb=1234"]), "Monitor and gdb can't be used together");
+    }
+}
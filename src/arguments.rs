@@ -7,7 +7,8 @@ pub(crate) fn get_args() -> Vec<String>
 
     if args.len() < 8 && args.len() != 2
     {
-        eprintln!("ROM filename, program filename, disk name, disk size, memory size, screen width, screen height");
+        eprintln!("ROM filename, program filename, disk name, disk size, memory size, screen width, screen height \
+                   [option=value...]");
         std::process::exit(1);
     }
 
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::process::{Child, Command, Stdio};
+
+use computer::Computer;
+use computer_config::Config;
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+// 16-bit PCM WAV, sizes patched in when finished
+struct WavWriter
+{
+    out: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavWriter
+{
+    fn create(filename: &str, sample_rate: u32, channels: u32) -> io::Result<WavWriter>
+    {
+        let mut out = BufWriter::new(File::create(filename)?);
+        let block_align = channels * 2;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?; // patched in finish
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&(channels as u16).to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align).to_le_bytes())?;
+        out.write_all(&(block_align as u16).to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?; // patched in finish
+
+        Ok(WavWriter { out, data_size: 0 })
+    }
+
+    fn write(&mut self, samples: &[u8]) -> io::Result<()>
+    {
+        self.data_size += samples.len() as u32;
+        self.out.write_all(samples)
+    }
+
+    fn finish(mut self) -> io::Result<()>
+    {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(WAV_HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+// where the guest's sound goes: a WAV file and/or a player command reading raw s16le from stdin
+pub(crate) struct AudioOutput
+{
+    wav: Option<WavWriter>,
+    player: Option<Child>,
+    bytes: Vec<u8>,
+}
+
+impl AudioOutput
+{
+    pub(crate) fn new(config: &Config) -> AudioOutput
+    {
+        let wav = config.audio_out().as_ref().and_then(|filename|
+            match WavWriter::create(filename, config.sample_rate(), config.audio_channels())
+            {
+                Ok(wav) => Some(wav),
+                Err(error) =>
+                {
+                    eprintln!("Cannot write audio to {filename}: {error}");
+                    None
+                },
+            });
+
+        // e.g. audio_player=aplay -q -t raw -f S16_LE -r 22050 -c 1
+        let player = config.audio_player().as_ref().and_then(|command|
+        {
+            let mut words = command.split_whitespace();
+            let program = words.next()?;
+            match Command::new(program).args(words).stdin(Stdio::piped()).spawn()
+            {
+                Ok(child) => Some(child),
+                Err(error) =>
+                {
+                    eprintln!("Cannot start audio player {program}: {error}");
+                    None
+                },
+            }
+        });
+
+        AudioOutput { wav, player, bytes: Vec::new() }
+    }
+
+    // passes on everything the sound device produced since the last call
+    pub(crate) fn update(&mut self, computer: &mut Computer)
+    {
+        let samples = computer.take_audio_samples();
+        if samples.is_empty() || (self.wav.is_none() && self.player.is_none())
+        {
+            return;
+        }
+
+        self.bytes.clear();
+        self.bytes.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+
+        if let Some(wav) = &mut self.wav
+        {
+            if let Err(error) = wav.write(&self.bytes)
+            {
+                eprintln!("Audio file write failed: {error}");
+                self.wav = None;
+            }
+        }
+
+        if let Some(player) = &mut self.player
+        {
+            let written = match &mut player.stdin
+            {
+                Some(stdin) => stdin.write_all(&self.bytes).is_ok(),
+                None => false,
+            };
+            if !written
+            {
+                eprintln!("Audio player stopped");
+                self.player = None;
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self)
+    {
+        if let Some(wav) = self.wav.take()
+        {
+            if let Err(error) = wav.finish()
+            {
+                eprintln!("Audio file write failed: {error}");
+            }
+        }
+
+        if let Some(mut player) = self.player.take()
+        {
+            drop(player.stdin.take()); // end of stream
+            let _ = player.wait();
+        }
+    }
+}
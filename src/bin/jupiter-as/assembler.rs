@@ -0,0 +1,673 @@
+// two passes over the parsed lines: the first sizes everything and places labels, the second encodes
+
+use std::collections::{HashMap, HashSet};
+
+use crate::parser::{Expression, Line, Operand, Part, Section, Statement, Term};
+
+const AT: u8 = 1; // scratch register of the pseudo-instructions
+
+pub(crate) struct Symbol
+{
+    pub(crate) name: String,
+    pub(crate) value: u32,
+    pub(crate) section: Option<Section>, // none for .equ constants
+    pub(crate) global: bool,
+}
+
+pub(crate) struct Image
+{
+    pub(crate) text: Vec<u8>,
+    pub(crate) data: Vec<u8>,
+    pub(crate) text_address: u32,
+    pub(crate) data_address: u32, // the data follows the text
+    pub(crate) symbols: Vec<Symbol>, // sorted by address
+}
+
+impl Image
+{
+    // what gets loaded, text, padding and data
+    pub(crate) fn bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = self.text.clone();
+        bytes.resize((self.data_address - self.text_address) as usize, 0);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+struct Assembler
+{
+    offsets: [u32; 2], // per section
+    alignments: [u32; 2],
+    section: Section,
+    symbols: HashMap<String, i64>,
+    errors: Vec<(usize, String)>, // line, 0 for the whole file
+}
+
+fn index(section: Section) -> usize
+{
+    match section
+    {
+        Section::Text => 0,
+        Section::Data => 1,
+    }
+}
+
+fn align_up(value: u32, alignment: u32) -> u32
+{
+    value.div_ceil(alignment) * alignment
+}
+
+fn fits_signed(value: i64) -> bool
+{
+    (i16::MIN as i64..=i16::MAX as i64).contains(&value)
+}
+
+fn fits_unsigned(value: i64) -> bool
+{
+    (0..=u16::MAX as i64).contains(&value)
+}
+
+fn fits_word(value: i64) -> bool
+{
+    (i32::MIN as i64..=u32::MAX as i64).contains(&value)
+}
+
+fn r_type(rs: u8, rt: u8, rd: u8, shift: u32, funct: u32) -> u32
+{
+    (rs as u32) << 21 | (rt as u32) << 16 | (rd as u32) << 11 | shift << 6 | funct
+}
+
+fn i_type(opcode: u32, rs: u8, rt: u8, imm: u32) -> u32
+{
+    opcode << 26 | (rs as u32) << 21 | (rt as u32) << 16 | (imm & 0xFFFF)
+}
+
+fn load_store_opcode(mnemonic: &str) -> Option<u32>
+{
+    match mnemonic
+    {
+        "lb" => Some(32),
+        "lh" => Some(33),
+        "lw" => Some(34),
+        "lbu" => Some(36),
+        "lhu" => Some(37),
+        "sb" => Some(40),
+        "sh" => Some(41),
+        "sw" => Some(43),
+        _ => None,
+    }
+}
+
+// words an instruction takes, known before any symbol is
+fn instruction_size(mnemonic: &str, operands: &[Operand]) -> u32
+{
+    match (mnemonic, operands)
+    {
+        ("li", [_, Operand::Expression(value)]) => match value.constant()
+        {
+            Some(value) if fits_signed(value) || fits_unsigned(value) || value & 0xFFFF == 0 => 1,
+            _ => 2,
+        },
+        ("la", _) => 2,
+        (_, [_, Operand::Expression(address)]) if load_store_opcode(mnemonic).is_some() =>
+            if address.constant().is_some_and(fits_signed) { 1 } else { 2 },
+        _ => 1,
+    }
+}
+
+impl Assembler
+{
+    fn offset(&mut self) -> &mut u32
+    {
+        &mut self.offsets[index(self.section)]
+    }
+
+    fn align(&mut self, alignment: u32)
+    {
+        let offset = self.offset();
+        *offset = align_up(*offset, alignment);
+        let current = &mut self.alignments[index(self.section)];
+        *current = (*current).max(alignment);
+    }
+
+    // bytes a statement takes, after aligning for it
+    fn place(&mut self, statement: &Statement) -> u32
+    {
+        match statement
+        {
+            Statement::Section(section) =>
+            {
+                self.section = *section;
+                0
+            },
+            Statement::Data(width, values) =>
+            {
+                self.align(*width);
+                width * values.len() as u32
+            },
+            Statement::Bytes(bytes) => bytes.len() as u32,
+            Statement::Align(power) =>
+            {
+                self.align(1 << power);
+                0
+            },
+            Statement::Space(size) => *size,
+            Statement::Global(_) | Statement::Equ(..) => 0,
+            Statement::Instruction(mnemonic, operands) =>
+            {
+                self.align(4);
+                4 * instruction_size(mnemonic, operands)
+            },
+        }
+    }
+
+    fn evaluate(&self, expression: &Expression) -> Result<i64, String>
+    {
+        let mut value: i64 = 0;
+        for (negated, term) in &expression.terms
+        {
+            let term = match term
+            {
+                Term::Number(number) => *number,
+                Term::Symbol(name) => *self.symbols.get(name).ok_or_else(|| format!("undefined symbol {name}"))?,
+            };
+            value = if *negated { value - term } else { value + term };
+        }
+        Ok(match expression.part
+        {
+            Part::Whole => value,
+            Part::Hi => ((value + 0x8000) >> 16) & 0xFFFF,
+            Part::Lo => value as i16 as i64,
+        })
+    }
+
+    // %hi() and %lo() fill the field whatever their sign
+    fn signed_field(&self, expression: &Expression) -> Result<u32, String>
+    {
+        let value = self.evaluate(expression)?;
+        if expression.part == Part::Whole && !fits_signed(value)
+        {
+            return Err(format!("immediate {value} out of range"));
+        }
+        Ok(value as u32 & 0xFFFF)
+    }
+
+    fn unsigned_field(&self, expression: &Expression) -> Result<u32, String>
+    {
+        let value = self.evaluate(expression)?;
+        if expression.part == Part::Whole && !fits_unsigned(value)
+        {
+            return Err(format!("immediate {value} out of range"));
+        }
+        Ok(value as u32 & 0xFFFF)
+    }
+
+    fn word(&self, expression: &Expression) -> Result<u32, String>
+    {
+        let value = self.evaluate(expression)?;
+        if !fits_word(value)
+        {
+            return Err(format!("value {value} does not fit in a word"));
+        }
+        Ok(value as u32)
+    }
+
+    fn shift(&self, expression: &Expression) -> Result<u32, String>
+    {
+        match self.evaluate(expression)?
+        {
+            shift @ 0..=31 => Ok(shift as u32),
+            shift => Err(format!("shift {shift} out of range")),
+        }
+    }
+
+    // the CPU adds the offset in bytes to the address of the next instruction
+    fn branch_offset(&self, target: &Expression, address: u32) -> Result<u32, String>
+    {
+        let target = self.word(target)?;
+        let offset = target.wrapping_sub(address.wrapping_add(4)) as i32 as i64;
+        if target % 4 != 0
+        {
+            return Err(format!("branch target {target:#x} not word aligned"));
+        }
+        if !fits_signed(offset)
+        {
+            return Err(format!("branch target {target:#x} out of range"));
+        }
+        Ok(offset as u32 & 0xFFFF)
+    }
+
+    fn jump_target(&self, target: &Expression, address: u32) -> Result<u32, String>
+    {
+        let target = self.word(target)?;
+        if target % 4 != 0
+        {
+            return Err(format!("jump target {target:#x} not word aligned"));
+        }
+        if target & 0xF000_0000 != address.wrapping_add(4) & 0xF000_0000
+        {
+            return Err(format!("jump target {target:#x} out of reach"));
+        }
+        Ok((target >> 2) & 0x03FF_FFFF)
+    }
+
+    fn encode(&self, mnemonic: &str, operands: &[Operand], address: u32) -> Result<Vec<u32>, String>
+    {
+        use Operand::*;
+
+        let words = match (mnemonic, operands)
+        {
+            ("nop", []) => vec![0],
+            ("syscall", []) => vec![12],
+            ("eret", []) => vec![0x4200_0018],
+
+            ("add" | "addu" | "sub" | "subu" | "and" | "or" | "xor" | "nor" | "slt" | "sltu",
+             [Register(rd), Register(rs), Register(rt)]) =>
+            {
+                let funct = match mnemonic
+                {
+                    "add" => 32,
+                    "addu" => 33,
+                    "sub" => 34,
+                    "subu" => 35,
+                    "and" => 36,
+                    "or" => 37,
+                    "xor" => 38,
+                    "nor" => 39,
+                    "slt" => 42,
+                    _ => 43,
+                };
+                vec![r_type(*rs, *rt, *rd, 0, funct)]
+            },
+            ("sll" | "srl" | "sra", [Register(rd), Register(rt), Expression(shift)]) =>
+            {
+                let funct = match mnemonic
+                {
+                    "sll" => 0,
+                    "srl" => 2,
+                    _ => 3,
+                };
+                vec![r_type(0, *rt, *rd, self.shift(shift)?, funct)]
+            },
+            ("sllv" | "srlv" | "srav", [Register(rd), Register(rt), Register(rs)]) =>
+            {
+                let funct = match mnemonic
+                {
+                    "sllv" => 4,
+                    "srlv" => 6,
+                    _ => 7,
+                };
+                vec![r_type(*rs, *rt, *rd, 0, funct)]
+            },
+            ("jr", [Register(rs)]) => vec![r_type(*rs, 0, 0, 0, 8)],
+            ("jalr", [Register(rs)]) => vec![r_type(*rs, 0, 31, 0, 9)],
+            ("jalr", [Register(rd), Register(rs)]) => vec![r_type(*rs, 0, *rd, 0, 9)],
+            ("mfhi", [Register(rd)]) => vec![r_type(0, 0, *rd, 0, 16)],
+            ("mthi", [Register(rs)]) => vec![r_type(*rs, 0, 0, 0, 17)],
+            ("mflo", [Register(rd)]) => vec![r_type(0, 0, *rd, 0, 18)],
+            ("mtlo", [Register(rs)]) => vec![r_type(*rs, 0, 0, 0, 19)],
+            ("mult" | "multu" | "div" | "divu", [Register(rs), Register(rt)]) =>
+            {
+                let funct = match mnemonic
+                {
+                    "mult" => 24,
+                    "multu" => 25,
+                    "div" => 26,
+                    _ => 27,
+                };
+                vec![r_type(*rs, *rt, 0, 0, funct)]
+            },
+
+            ("j" | "jal", [Expression(target)]) =>
+            {
+                let opcode = if mnemonic == "j" { 2 } else { 3 };
+                vec![opcode << 26 | self.jump_target(target, address)?]
+            },
+            ("beq" | "bne", [Register(rs), Register(rt), Expression(target)]) =>
+            {
+                let opcode = if mnemonic == "beq" { 4 } else { 5 };
+                vec![i_type(opcode, *rs, *rt, self.branch_offset(target, address)?)]
+            },
+            ("blez" | "bgtz", [Register(rs), Expression(target)]) =>
+            {
+                let opcode = if mnemonic == "blez" { 6 } else { 7 };
+                vec![i_type(opcode, *rs, 0, self.branch_offset(target, address)?)]
+            },
+            ("addi" | "addiu" | "slti" | "sltiu", [Register(rt), Register(rs), Expression(imm)]) =>
+            {
+                let opcode = match mnemonic
+                {
+                    "addi" => 8,
+                    "addiu" => 9,
+                    "slti" => 10,
+                    _ => 11,
+                };
+                vec![i_type(opcode, *rs, *rt, self.signed_field(imm)?)]
+            },
+            ("andi" | "ori" | "xori", [Register(rt), Register(rs), Expression(imm)]) =>
+            {
+                let opcode = match mnemonic
+                {
+                    "andi" => 12,
+                    "ori" => 13,
+                    _ => 14,
+                };
+                vec![i_type(opcode, *rs, *rt, self.unsigned_field(imm)?)]
+            },
+            ("lui", [Register(rt), Expression(imm)]) => vec![i_type(15, 0, *rt, self.unsigned_field(imm)?)],
+            ("mfc0" | "mtc0", [Register(rt), Register(rd), select @ ..]) if select.len() <= 1 =>
+            {
+                let select = match select
+                {
+                    [Expression(select)] => match self.evaluate(select)?
+                    {
+                        select @ 0..=7 => select as u32,
+                        select => return Err(format!("select {select} out of range")),
+                    },
+                    [] => 0,
+                    _ => return Err(format!("bad operands for {mnemonic}")),
+                };
+                let rs = if mnemonic == "mfc0" { 0 } else { 4 };
+                vec![16 << 26 | r_type(rs, *rt, *rd, 0, select)]
+            },
+            (_, [Register(rt), Memory(offset, base)]) if load_store_opcode(mnemonic).is_some() =>
+            {
+                let opcode = load_store_opcode(mnemonic).unwrap_or_default();
+                vec![i_type(opcode, *base, *rt, self.signed_field(offset)?)]
+            },
+            (_, [Register(rt), Expression(address)]) if load_store_opcode(mnemonic).is_some() =>
+            {
+                let opcode = load_store_opcode(mnemonic).unwrap_or_default();
+                let value = self.word(address)?;
+                if instruction_size(mnemonic, operands) == 1
+                {
+                    vec![i_type(opcode, 0, *rt, value)]
+                }
+                else
+                {
+                    let hi = (value.wrapping_add(0x8000) >> 16) & 0xFFFF;
+                    vec![i_type(15, 0, AT, hi), i_type(opcode, AT, *rt, value)]
+                }
+            },
+
+            // pseudo-instructions
+            ("move", [Register(rd), Register(rs)]) => vec![r_type(*rs, 0, *rd, 0, 33)],
+            ("b", [Expression(target)]) => vec![i_type(4, 0, 0, self.branch_offset(target, address)?)],
+            ("beqz" | "bnez", [Register(rs), Expression(target)]) =>
+            {
+                let opcode = if mnemonic == "beqz" { 4 } else { 5 };
+                vec![i_type(opcode, *rs, 0, self.branch_offset(target, address)?)]
+            },
+            ("li" | "la", [Register(rt), Expression(value)]) =>
+            {
+                let value = self.word(value)?;
+                let signed = value as i32 as i64;
+                match instruction_size(mnemonic, operands)
+                {
+                    2 => vec![i_type(15, 0, *rt, value >> 16), i_type(13, *rt, *rt, value)],
+                    _ if fits_signed(signed) => vec![i_type(9, 0, *rt, value)],
+                    _ if fits_unsigned(value as i64) => vec![i_type(13, 0, *rt, value)],
+                    _ => vec![i_type(15, 0, *rt, value >> 16)],
+                }
+            },
+
+            _ if Self::known(mnemonic) => return Err(format!("bad operands for {mnemonic}")),
+            _ => return Err(format!("unknown instruction {mnemonic}")),
+        };
+        Ok(words)
+    }
+
+    fn known(mnemonic: &str) -> bool
+    {
+        const MNEMONICS: &[&str] = &[
+            "nop", "syscall", "eret", "add", "addu", "sub", "subu", "and", "or", "xor", "nor", "slt", "sltu",
+            "sll", "srl", "sra", "sllv", "srlv", "srav", "jr", "jalr", "mfhi", "mthi", "mflo", "mtlo", "mult",
+            "multu", "div", "divu", "j", "jal", "beq", "bne", "blez", "bgtz", "addi", "addiu", "slti", "sltiu",
+            "andi", "ori", "xori", "lui", "mfc0", "mtc0", "move", "b", "beqz", "bnez", "li", "la",
+        ];
+        MNEMONICS.contains(&mnemonic) || load_store_opcode(mnemonic).is_some()
+    }
+
+    fn error(&mut self, line: &Line, message: String)
+    {
+        self.errors.push((line.number, message));
+    }
+}
+
+// base is where the image is loaded, predefined gives the memory map symbols for an image of the given size
+pub(crate) fn assemble(lines: &[Line], base: u32, predefined: &dyn Fn(u32) -> Vec<(&'static str, u32)>)
+    -> Result<Image, Vec<(usize, String)>>
+{
+    let mut assembler = Assembler
+    {
+        offsets: [0; 2],
+        alignments: [4; 2],
+        section: Section::Text,
+        symbols: HashMap::new(),
+        errors: Vec::new(),
+    };
+
+    // first pass, labels become section offsets
+    let mut labels: Vec<(String, Section, u32, &Line)> = Vec::new();
+    let mut constants: Vec<(String, &Expression, &Line)> = Vec::new();
+    let mut globals = HashSet::new();
+    for line in lines
+    {
+        // the statement aligns first, so labels on its line point at it
+        let size = match &line.statement
+        {
+            Some(statement) => assembler.place(statement),
+            None => 0,
+        };
+        let offset = *assembler.offset();
+        for label in &line.labels
+        {
+            labels.push((label.clone(), assembler.section, offset, line));
+        }
+        *assembler.offset() = offset + size;
+
+        match &line.statement
+        {
+            Some(Statement::Global(name)) =>
+            {
+                globals.insert(name.clone());
+            },
+            Some(Statement::Equ(name, value)) => constants.push((name.clone(), value, line)),
+            _ => {},
+        }
+    }
+
+    let text_size = assembler.offsets[0];
+    let text_alignment = assembler.alignments[0];
+    let data_address = align_up(base + text_size, assembler.alignments[1]);
+    let image_size = data_address - base + assembler.offsets[1];
+    if !base.is_multiple_of(text_alignment)
+    {
+        assembler.errors.push((0, format!("base {base:#x} is not aligned to {text_alignment}")));
+    }
+    let section_address = |section| if section == Section::Text { base } else { data_address };
+
+    for (name, address) in predefined(image_size)
+    {
+        assembler.symbols.insert(name.to_string(), address as i64);
+    }
+    let mut symbols = Vec::new();
+    for (name, section, offset, line) in labels
+    {
+        let address = section_address(section) + offset;
+        if assembler.symbols.insert(name.clone(), address as i64).is_some()
+        {
+            assembler.error(line, format!("{name} defined twice"));
+        }
+        symbols.push(Symbol { global: globals.contains(&name), name, value: address, section: Some(section) });
+    }
+    for (name, value, line) in constants
+    {
+        let value = match assembler.evaluate(value)
+        {
+            Ok(value) if fits_word(value) => value,
+            Ok(value) =>
+            {
+                assembler.error(line, format!("value {value} does not fit in a word"));
+                continue;
+            },
+            Err(message) =>
+            {
+                assembler.error(line, message);
+                continue;
+            },
+        };
+        if assembler.symbols.insert(name.clone(), value).is_some()
+        {
+            assembler.error(line, format!("{name} defined twice"));
+        }
+        symbols.push(Symbol { global: globals.contains(&name), name, value: value as u32, section: None });
+    }
+    for name in &globals
+    {
+        if !symbols.iter().any(|symbol| &symbol.name == name)
+        {
+            assembler.errors.push((0, format!("global {name} is not defined")));
+        }
+    }
+    symbols.sort_by_key(|symbol| (symbol.value, symbol.section.is_none()));
+
+    // second pass, encoding
+    let mut sections: [Vec<u8>; 2] = [Vec::with_capacity(text_size as usize), Vec::new()];
+    assembler.offsets = [0; 2];
+    assembler.section = Section::Text;
+    for line in lines
+    {
+        let statement = match &line.statement
+        {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let size = assembler.place(statement);
+        let section = index(assembler.section);
+        let offset = *assembler.offset();
+        let address = section_address(assembler.section) + offset;
+
+        let encoded: Result<Vec<u8>, String> = match statement
+        {
+            Statement::Data(width, values) => values.iter()
+                .map(|value| assembler.word(value).and_then(|word| match width
+                {
+                    4 => Ok(word.to_be_bytes().to_vec()),
+                    2 if word <= 0xFFFF || word >= 0xFFFF_8000 => Ok((word as u16).to_be_bytes().to_vec()),
+                    1 if word <= 0xFF || word >= 0xFFFF_FF80 => Ok(vec![word as u8]),
+                    2 => Err(format!("value {} does not fit in a half", word as i32)),
+                    _ => Err(format!("value {} does not fit in a byte", word as i32)),
+                }))
+                .collect::<Result<Vec<Vec<u8>>, String>>()
+                .map(|values| values.concat()),
+            Statement::Bytes(data) => Ok(data.clone()),
+            Statement::Space(size) => Ok(vec![0; *size as usize]),
+            Statement::Instruction(mnemonic, operands) => assembler.encode(mnemonic, operands, address)
+                .map(|words| words.iter().flat_map(|word| word.to_be_bytes()).collect()),
+            _ => Ok(Vec::new()),
+        };
+
+        let bytes = &mut sections[section];
+        bytes.resize(offset as usize, 0); // alignment padding
+        match encoded
+        {
+            Ok(encoded) => bytes.extend_from_slice(&encoded),
+            Err(message) =>
+            {
+                bytes.resize((offset + size) as usize, 0);
+                assembler.error(line, message);
+            },
+        }
+        *assembler.offset() = offset + size;
+    }
+
+    if !assembler.errors.is_empty()
+    {
+        assembler.errors.sort_by_key(|(line, _)| *line);
+        return Err(assembler.errors);
+    }
+    let [text, data] = sections;
+    Ok(Image { text, data, text_address: base, data_address, symbols })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::parser::parse_line;
+
+    fn assemble_at(source: &str, base: u32) -> Result<Image, Vec<(usize, String)>>
+    {
+        let lines: Vec<Line> = source.lines().enumerate().map(|(i, line)| parse_line(i + 1, line).unwrap()).collect();
+        assemble(&lines, base, &|size| vec![("VRAM", 0x8000), ("END", 0x1000 + size)])
+    }
+
+    fn words(source: &str, base: u32) -> Vec<u32>
+    {
+        let image = assemble_at(source, base).unwrap_or_else(|errors| panic!("{errors:?}"));
+        image.text.chunks(4).map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]])).collect()
+    }
+
+    fn errors(source: &str) -> Vec<(usize, String)>
+    {
+        assemble_at(source, 0).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn instructions_encode_as_the_cpu_decodes_them()
+    {
+        let source = "addu $t2, $t0, $t1\n addiu $sp, $sp, -8\n lw $ra, 4($sp)\n sw $a0, -4($sp)\n\
+                      beq $t0, $zero, end\n jal end\n lui $at, 0x1234\n ori $t0, $t0, 0xffff\n sll $t0, $t1, 3\n\
+                      jr $ra\n end: eret\n mfc0 $k0, $12\n mtc0 $k1, $14, 0\n jalr $t9\n mult $a0, $a1\n syscall";
+        assert_eq!(words(source, 0), [
+            0x0109_5021, 0x27bd_fff8, 0x8bbf_0004, 0xafa4_fffc, 0x1100_0014, 0x0c00_000a, 0x3c01_1234, 0x3508_ffff,
+            0x0009_40c0, 0x03e0_0008, 0x4200_0018, 0x401a_6000, 0x409b_7000, 0x0320_f809, 0x0085_0018, 0x0000_000c,
+        ]);
+
+        // branch offsets count bytes, jump targets words
+        assert_eq!(words("start: b start\n j start", 0x1000), [0x1000_fffc, 0x0800_0400]);
+    }
+
+    #[test]
+    fn pseudo_instructions_pick_the_shortest_form()
+    {
+        assert_eq!(words("li $t0, -5\n li $t0, 0xffff\n li $t0, 0x12340000\n li $t0, 0x12345678", 0),
+                   [0x2408_fffb, 0x3408_ffff, 0x3c08_1234, 0x3c08_1234, 0x3508_5678]);
+        assert_eq!(words("move $v0, $a0\n beqz $v0, 0\n la $t0, VRAM", 0),
+                   [0x0080_1021, 0x1040_fff8, 0x3c08_0000, 0x3508_8000]);
+
+        // an address load splits so the sign extended low half adds up
+        assert_eq!(words("lw $t0, 0x12348000\n sb $t1, 16", 0), [0x3c01_1235, 0x8828_8000, 0xa009_0010]);
+    }
+
+    #[test]
+    fn data_follows_the_text_aligned()
+    {
+        let image = assemble_at(".data\n a: .byte 1\n b: .half 2\n .align 3\n c: .word c, END\n .text\n nop", 0x100)
+            .unwrap_or_else(|errors| panic!("{errors:?}"));
+        assert_eq!((image.text_address, image.data_address), (0x100, 0x108));
+        assert_eq!(image.data, [1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0x01, 0x10, 0, 0, 0x10, 0x18]);
+        let symbols: Vec<(&str, u32)> = image.symbols.iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.value))
+            .collect();
+        assert_eq!(symbols, [("a", 0x108), ("b", 0x10a), ("c", 0x110)]);
+        assert_eq!(image.bytes().len(), 0x18);
+    }
+
+    #[test]
+    fn errors_name_their_line()
+    {
+        assert_eq!(errors("nop\n j nowhere"), [(2, "undefined symbol nowhere".to_string())]);
+        assert_eq!(errors("a: nop\na: nop"), [(2, "a defined twice".to_string())]);
+        assert_eq!(errors("addiu $t0, $t0, 0x8000"), [(1, "immediate 32768 out of range".to_string())]);
+        assert_eq!(errors("beq $t0, $t1, 0x20002"), [(1, "branch target 0x20002 not word aligned".to_string())]);
+        assert_eq!(errors("beq $t0, $t1, 0x20004"), [(1, "branch target 0x20004 out of range".to_string())]);
+        assert_eq!(errors("j 0x10000000"), [(1, "jump target 0x10000000 out of reach".to_string())]);
+        assert_eq!(errors("sll $t0, $t0, 32"), [(1, "shift 32 out of range".to_string())]);
+        assert_eq!(errors("addu $t0, $t1"), [(1, "bad operands for addu".to_string())]);
+        assert_eq!(errors("frob $t0"), [(1, "unknown instruction frob".to_string())]);
+        assert_eq!(assemble_at("nop", 2).err().unwrap_or_default(), [(0, "base 0x2 is not aligned to 4".to_string())]);
+    }
+}
@@ -0,0 +1,150 @@
+// 32-bit big-endian MIPS executable: one loadable segment with the image, .text, .data and a symbol table
+
+use crate::assembler::Image;
+use crate::parser::Section;
+
+const HEADER_SIZE: u32 = 52;
+const PROGRAM_HEADER_SIZE: u32 = 32;
+const SECTION_HEADER_SIZE: u32 = 40;
+const SYMBOL_SIZE: u32 = 16;
+
+const EM_MIPS: u16 = 8;
+const ET_EXEC: u16 = 2;
+const PT_LOAD: u32 = 1;
+const PF_RWX: u32 = 7;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const SHN_ABS: u16 = 0xFFF1;
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+// section header indices
+const TEXT: u16 = 1;
+const DATA: u16 = 2;
+const STRTAB: u32 = 4;
+
+fn push_u16(out: &mut Vec<u8>, value: u16)
+{
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32)
+{
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn pad(out: &mut Vec<u8>)
+{
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+// offset of the name in the string table
+fn add_string(table: &mut Vec<u8>, name: &str) -> u32
+{
+    let offset = table.len() as u32;
+    table.extend_from_slice(name.as_bytes());
+    table.push(0);
+    offset
+}
+
+#[allow(clippy::too_many_arguments)]
+fn section_header(out: &mut Vec<u8>, name: u32, kind: u32, flags: u32, address: u32, offset: u32, size: u32, link: u32,
+                  info: u32, entry_size: u32)
+{
+    for value in [name, kind, flags, address, offset, size, link, info, 4, entry_size]
+    {
+        push_u32(out, value);
+    }
+}
+
+pub(crate) fn write_elf(image: &Image) -> Vec<u8>
+{
+    let loaded = image.bytes();
+    let image_offset = HEADER_SIZE + PROGRAM_HEADER_SIZE;
+    let data_offset = image_offset + image.data_address - image.text_address;
+
+    // locals have to come before globals
+    let mut strings = vec![0];
+    let mut symbols = vec![0; SYMBOL_SIZE as usize];
+    let mut ordered: Vec<_> = image.symbols.iter().filter(|symbol| !symbol.global).collect();
+    let first_global = ordered.len() as u32 + 1;
+    ordered.extend(image.symbols.iter().filter(|symbol| symbol.global));
+    for symbol in ordered
+    {
+        let (kind, index) = match symbol.section
+        {
+            Some(Section::Text) => (STT_FUNC, TEXT),
+            Some(Section::Data) => (STT_OBJECT, DATA),
+            None => (STT_NOTYPE, SHN_ABS),
+        };
+        let binding = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+        push_u32(&mut symbols, add_string(&mut strings, &symbol.name));
+        push_u32(&mut symbols, symbol.value);
+        push_u32(&mut symbols, 0); // size
+        symbols.push(binding << 4 | kind);
+        symbols.push(0); // visibility
+        push_u16(&mut symbols, index);
+    }
+
+    let mut section_names = vec![0];
+    let names: Vec<u32> = [".text", ".data", ".symtab", ".strtab", ".shstrtab"].iter()
+        .map(|name| add_string(&mut section_names, name))
+        .collect();
+
+    let mut out = Vec::new();
+
+    // file header
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 2, 1, 0]); // 32 bit, big endian, version 1
+    out.resize(16, 0);
+    push_u16(&mut out, ET_EXEC);
+    push_u16(&mut out, EM_MIPS);
+    push_u32(&mut out, 1); // version
+    push_u32(&mut out, image.text_address); // entry
+    push_u32(&mut out, HEADER_SIZE); // program headers
+    let section_headers_at = out.len();
+    push_u32(&mut out, 0); // section headers, filled in below
+    push_u32(&mut out, 0); // flags
+    push_u16(&mut out, HEADER_SIZE as u16);
+    push_u16(&mut out, PROGRAM_HEADER_SIZE as u16);
+    push_u16(&mut out, 1);
+    push_u16(&mut out, SECTION_HEADER_SIZE as u16);
+    push_u16(&mut out, 6);
+    push_u16(&mut out, 5); // .shstrtab
+
+    // program header
+    for value in [PT_LOAD, image_offset, image.text_address, image.text_address, loaded.len() as u32,
+                  loaded.len() as u32, PF_RWX, 4]
+    {
+        push_u32(&mut out, value);
+    }
+
+    out.extend_from_slice(&loaded);
+    pad(&mut out);
+    let symbols_offset = out.len() as u32;
+    out.extend_from_slice(&symbols);
+    let strings_offset = out.len() as u32;
+    out.extend_from_slice(&strings);
+    let section_names_offset = out.len() as u32;
+    out.extend_from_slice(&section_names);
+    pad(&mut out);
+
+    let section_headers = out.len() as u32;
+    out[section_headers_at..section_headers_at + 4].copy_from_slice(&section_headers.to_be_bytes());
+    out.extend_from_slice(&[0; SECTION_HEADER_SIZE as usize]);
+    section_header(&mut out, names[0], SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, image.text_address, image_offset,
+                   image.text.len() as u32, 0, 0, 0);
+    section_header(&mut out, names[1], SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, image.data_address, data_offset,
+                   image.data.len() as u32, 0, 0, 0);
+    section_header(&mut out, names[2], SHT_SYMTAB, 0, 0, symbols_offset, symbols.len() as u32, STRTAB, first_global,
+                   SYMBOL_SIZE);
+    section_header(&mut out, names[3], SHT_STRTAB, 0, 0, strings_offset, strings.len() as u32, 0, 0, 0);
+    section_header(&mut out, names[4], SHT_STRTAB, 0, 0, section_names_offset, section_names.len() as u32, 0, 0, 0);
+    out
+}
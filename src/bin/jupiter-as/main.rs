@@ -0,0 +1,234 @@
+// assembles MIPS source into a raw big-endian ROM or program image, or an ELF executable
+
+mod parser;
+mod assembler;
+mod elf;
+
+use std::fs;
+
+use computer::memory_map;
+use computer_config::Config;
+
+use assembler::{assemble, Image};
+use parser::parse_line;
+
+const USAGE: &str = "source filename, output filename [format=raw|elf] [image=rom|program] [machine=CONFIG] \
+[base=ADDR] [map=FILE]
+machine is an emulator config file, it defines the memory map symbols and where programs are loaded";
+
+fn usage() -> !
+{
+    eprintln!("{USAGE}");
+    std::process::exit(1);
+}
+
+fn fail(message: &str) -> !
+{
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+// line 0 is about the whole file
+fn report(source_filename: &str, errors: &[(usize, String)]) -> !
+{
+    for (line, message) in errors
+    {
+        match line
+        {
+            0 => eprintln!("{source_filename}: {message}"),
+            line => eprintln!("{source_filename}:{line}: {message}"),
+        }
+    }
+    std::process::exit(1);
+}
+
+fn parse_address(text: &str) -> Option<u32>
+{
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// programs are loaded at PROGRAM, after the machine's regions and the ROM it boots from
+fn program_base(config: &Config, rom_size: u32) -> u32
+{
+    memory_map(config, rom_size).iter()
+        .find(|(name, _)| *name == "PROGRAM")
+        .map(|(_, address)| *address)
+        .unwrap_or_default()
+}
+
+// a line that did not parse would only add undefined symbols, so assembling waits for a clean parse
+fn assemble_source(source: &str, base: u32, predefined: &dyn Fn(u32) -> Vec<(&'static str, u32)>)
+    -> Result<Image, Vec<(usize, String)>>
+{
+    let mut errors = Vec::new();
+    let mut lines = Vec::new();
+    for (i, line) in source.lines().enumerate()
+    {
+        match parse_line(i + 1, line)
+        {
+            Ok(line) => lines.push(line),
+            Err(message) => errors.push((i + 1, message)),
+        }
+    }
+    if !errors.is_empty()
+    {
+        return Err(errors);
+    }
+    assemble(&lines, base, predefined)
+}
+
+// "address name" per line, labels only
+fn symbol_map(image: &Image) -> String
+{
+    image.symbols.iter()
+        .filter(|symbol| symbol.section.is_some())
+        .map(|symbol| format!("{:08x} {}\n", symbol.value, symbol.name))
+        .collect()
+}
+
+fn main()
+{
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3
+    {
+        usage();
+    }
+    let (source_filename, output_filename) = (&args[1], &args[2]);
+
+    let (mut elf, mut program, mut machine, mut base, mut map) = (false, false, None, None, None);
+    for arg in &args[3..]
+    {
+        let (key, value) = arg.split_once('=').unwrap_or_else(|| usage());
+        match (key, value)
+        {
+            ("format", "raw") => elf = false,
+            ("format", "elf") => elf = true,
+            ("image", "rom") => program = false,
+            ("image", "program") => program = true,
+            ("machine", _) => machine = Some(Config::from_args(vec![args[0].clone(), value.to_string()])),
+            ("base", _) => base = Some(parse_address(value).unwrap_or_else(|| fail("Bad base address"))),
+            ("map", _) => map = Some(value.to_string()),
+            _ => usage(),
+        }
+    }
+
+    // programs are loaded after the ROM the machine boots from
+    let rom_size = match machine.as_ref().and_then(|config| config.rom_filename().as_ref())
+    {
+        Some(rom) if program => match fs::metadata(rom)
+        {
+            Ok(metadata) => metadata.len() as u32,
+            Err(error) => fail(&format!("Cannot read ROM {rom}: {error}")),
+        },
+        _ => 0,
+    };
+    let predefined = |image_size: u32| match &machine
+    {
+        Some(config) => memory_map(config, if program { rom_size } else { image_size }),
+        None => Vec::new(),
+    };
+    let base = match (base, &machine)
+    {
+        (Some(base), _) => base,
+        (None, _) if !program => 0,
+        (None, Some(config)) => program_base(config, rom_size),
+        (None, None) => fail("Program images need machine=CONFIG or base=ADDR"),
+    };
+
+    let source = match fs::read_to_string(source_filename)
+    {
+        Ok(source) => source,
+        Err(error) => fail(&format!("Cannot read {source_filename}: {error}")),
+    };
+
+    let image = assemble_source(&source, base, &predefined).unwrap_or_else(|errors| report(source_filename, &errors));
+
+    if let Some(config) = &machine
+    {
+        let end = image.data_address as u64 + image.data.len() as u64;
+        if end > config.memory_size() as u64
+        {
+            fail(&format!("Image ends at {end:#x}, past the end of memory"));
+        }
+    }
+
+    let output = if elf { elf::write_elf(&image) } else { image.bytes() };
+    if let Err(error) = fs::write(output_filename, output)
+    {
+        fail(&format!("Cannot write {output_filename}: {error}"));
+    }
+    if let Some(map) = map
+    {
+        if let Err(error) = fs::write(&map, symbol_map(&image))
+        {
+            fail(&format!("Cannot write {map}: {error}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use computer::disassemble;
+
+    fn machine(width: &str, height: &str) -> Config
+    {
+        let args = ["jupiter", "none", "none", "disk.img", "1M", "4M", width, height];
+        Config::from_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn program_images_assemble_for_common_machines()
+    {
+        let source = "start: la $t0, value\n lw $t1, 0($t0)\n j start\n .data\nvalue: .word 7\n";
+        for (width, height) in [("320", "200"), ("640", "480"), ("64", "48")]
+        {
+            let config = machine(width, height);
+            for rom_size in [0, 0x123, 0x1000]
+            {
+                let base = program_base(&config, rom_size);
+                assert_eq!(base % 4, 0, "{width}x{height} with a {rom_size} byte ROM");
+                let image = assemble_source(source, base, &|_| memory_map(&config, rom_size))
+                    .unwrap_or_else(|errors| panic!("{width}x{height}: {errors:?}"));
+                assert_eq!(image.text_address, base);
+                assert_eq!(image.data_address % 4, 0);
+            }
+        }
+    }
+
+    // every instruction the CPU runs, assembled, disassembled and assembled again
+    #[test]
+    fn disassembly_assembles_to_the_same_words()
+    {
+        let source = "top: sll $t0, $t1, 31\n srl $t0, $t1, 1\n sra $t0, $t1, 2\n sllv $t0, $t1, $t2\n\
+                      srlv $t0, $t1, $t2\n srav $t0, $t1, $t2\n jr $ra\n jalr $s0, $t9\n syscall\n mfhi $v0\n\
+                      mthi $v0\n mflo $v1\n\
+                      mtlo $v1\n mult $a0, $a1\n multu $a0, $a1\n div $a0, $a1\n divu $a0, $a1\n add $t0, $t1, $t2\n\
+                      addu $t0, $t1, $t2\n sub $t0, $t1, $t2\n subu $t0, $t1, $t2\n and $t0, $t1, $t2\n\
+                      or $t0, $t1, $t2\n xor $t0, $t1, $t2\n nor $t0, $t1, $t2\n slt $t0, $t1, $t2\n\
+                      sltu $t0, $t1, $t2\n j top\n jal bottom\n beq $t0, $t1, top\n bne $t0, $t1, bottom\n\
+                      blez $t0, top\n bgtz $t0, bottom\n addi $sp, $sp, -32768\n addiu $sp, $sp, 32767\n\
+                      slti $t0, $t1, -1\n sltiu $t0, $t1, 1\n andi $t0, $t1, 0xffff\n ori $t0, $t1, 0x8000\n\
+                      xori $t0, $t1, 1\n lui $gp, 0xffff\n mfc0 $k0, $13, 0\n mtc0 $k0, $12, 7\n eret\n\
+                      lb $t0, -1($sp)\n lh $t0, 2($sp)\n lw $t0, 4($sp)\n lbu $t0, 0($fp)\n lhu $t0, 6($fp)\n\
+                      sb $t0, 7($fp)\n sh $t0, -2($fp)\n bottom: sw $t0, 8($fp)\n nop";
+        let base = 0x1000;
+        let words = |source: &str| -> Vec<u32>
+        {
+            let image = assemble_source(source, base, &|_| Vec::new()).unwrap_or_else(|errors| panic!("{errors:?}"));
+            image.text.chunks(4).map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]])).collect()
+        };
+
+        let assembled = words(source);
+        let listing: Vec<String> = assembled.iter().enumerate()
+            .map(|(i, &word)| disassemble(word, base + 4 * i as u32))
+            .collect();
+        assert!(listing.iter().all(|line| !line.starts_with(".word")), "{listing:?}");
+        assert_eq!(words(&listing.join("\n")), assembled, "{listing:?}");
+    }
+}
@@ -0,0 +1,502 @@
+// source lines to statements, no symbol is resolved here
+
+use computer::REGISTER_NAMES;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Section
+{
+    Text,
+    Data,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Part
+{
+    Whole,
+    Hi, // upper half, adjusted for the sign extended lower half
+    Lo,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Term
+{
+    Number(i64),
+    Symbol(String),
+}
+
+// sum of terms, optionally cut to a half with %hi() or %lo()
+#[derive(Clone, Debug)]
+pub(crate) struct Expression
+{
+    pub(crate) terms: Vec<(bool, Term)>, // negated, term
+    pub(crate) part: Part,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Operand
+{
+    Register(u8),
+    Expression(Expression),
+    Memory(Expression, u8), // offset, base register
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Statement
+{
+    Section(Section),
+    Data(u32, Vec<Expression>), // width in bytes
+    Bytes(Vec<u8>),
+    Align(u32), // power of two
+    Space(u32),
+    Global(String),
+    Equ(String, Expression),
+    Instruction(String, Vec<Operand>),
+}
+
+pub(crate) struct Line
+{
+    pub(crate) number: usize,
+    pub(crate) labels: Vec<String>,
+    pub(crate) statement: Option<Statement>,
+}
+
+impl Expression
+{
+    pub(crate) fn number(value: i64) -> Expression
+    {
+        Expression { terms: vec![(false, Term::Number(value))], part: Part::Whole }
+    }
+
+    // value known without any symbol
+    pub(crate) fn constant(&self) -> Option<i64>
+    {
+        if self.part != Part::Whole
+        {
+            return None;
+        }
+        self.terms.iter().try_fold(0i64, |sum, (negated, term)| match term
+        {
+            Term::Number(value) => Some(if *negated { sum - value } else { sum + value }),
+            Term::Symbol(_) => None,
+        })
+    }
+}
+
+fn is_identifier(text: &str) -> bool
+{
+    let mut chars = text.chars();
+    match chars.next()
+    {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+pub(crate) fn parse_register(text: &str) -> Option<u8>
+{
+    let name = text.strip_prefix('$')?;
+    if let Ok(number) = name.parse::<u8>()
+    {
+        return if number < 32 { Some(number) } else { None };
+    }
+    if name == "s8"
+    {
+        return Some(30);
+    }
+    REGISTER_NAMES.iter().position(|register| &register[1..] == name).map(|index| index as u8)
+}
+
+fn parse_number(text: &str) -> Option<i64>
+{
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B"))
+    {
+        return i64::from_str_radix(binary, 2).ok();
+    }
+    text.parse().ok()
+}
+
+// the escapes of C strings that matter for programs: \n \t \r \0 \\ \" \'
+fn unescape(text: &str) -> Result<Vec<u8>, String>
+{
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next()
+    {
+        if c != '\\'
+        {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next()
+        {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            other => return Err(format!("bad escape \\{}", other.map(String::from).unwrap_or_default())),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+fn parse_term(text: &str) -> Result<Term, String>
+{
+    if let Some(quoted) = text.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\''))
+    {
+        return match unescape(quoted)?.as_slice()
+        {
+            [byte] => Ok(Term::Number(*byte as i64)),
+            _ => Err(format!("bad character {text}")),
+        };
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return parse_number(text).map(Term::Number).ok_or_else(|| format!("bad number {text}"));
+    }
+    if is_identifier(text)
+    {
+        return Ok(Term::Symbol(text.to_string()));
+    }
+    Err(format!("bad expression {text}"))
+}
+
+pub(crate) fn parse_expression(text: &str) -> Result<Expression, String>
+{
+    let text = text.trim();
+    for (prefix, part) in [("%hi(", Part::Hi), ("%lo(", Part::Lo)]
+    {
+        if let Some(inner) = text.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(')'))
+        {
+            let expression = parse_expression(inner)?;
+            if expression.part != Part::Whole
+            {
+                return Err(format!("bad expression {text}"));
+            }
+            return Ok(Expression { terms: expression.terms, part });
+        }
+    }
+
+    // split on + and - outside character literals
+    let mut terms = Vec::new();
+    let (mut start, mut negated, mut quoted) = (0, false, false);
+    for (i, c) in text.char_indices()
+    {
+        match c
+        {
+            '\'' => quoted = !quoted,
+            '+' | '-' if !quoted =>
+            {
+                let term = text[start..i].trim();
+                if term.is_empty()
+                {
+                    if start != 0 // only a leading sign stands alone
+                    {
+                        return Err(format!("bad expression {text}"));
+                    }
+                }
+                else
+                {
+                    terms.push((negated, parse_term(term)?));
+                }
+                negated = c == '-';
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    let term = text[start..].trim();
+    if term.is_empty()
+    {
+        return Err(format!("bad expression {text}"));
+    }
+    terms.push((negated, parse_term(term)?));
+    Ok(Expression { terms, part: Part::Whole })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String>
+{
+    if text.starts_with('$')
+    {
+        return parse_register(text).map(Operand::Register).ok_or_else(|| format!("bad register {text}"));
+    }
+
+    // offset(base), the offset may itself be %lo(...)
+    if let Some(open) = text.rfind('(').filter(|_| text.ends_with(')'))
+    {
+        let base = text[open + 1..text.len() - 1].trim();
+        if base.starts_with('$')
+        {
+            let base = parse_register(base).ok_or_else(|| format!("bad register {base}"))?;
+            let offset = text[..open].trim();
+            let offset = if offset.is_empty() { Expression::number(0) } else { parse_expression(offset)? };
+            return Ok(Operand::Memory(offset, base));
+        }
+    }
+
+    parse_expression(text).map(Operand::Expression)
+}
+
+// commas inside strings, characters and parentheses do not separate
+fn split_operands(text: &str) -> Vec<&str>
+{
+    let mut operands = Vec::new();
+    let (mut start, mut depth, mut quote) = (0, 0, None);
+    let mut escaped = false;
+    for (i, c) in text.char_indices()
+    {
+        match (quote, c)
+        {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 =>
+            {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    let last = text[start..].trim();
+    if !last.is_empty() || !operands.is_empty()
+    {
+        operands.push(last);
+    }
+    operands
+}
+
+// the line without its # comment, strings and characters may contain #
+fn strip_comment(line: &str) -> &str
+{
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices()
+    {
+        match (quote, c)
+        {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {},
+        }
+    }
+    line
+}
+
+fn parse_string(text: &str) -> Result<Vec<u8>, String>
+{
+    match text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+    {
+        Some(inner) => unescape(inner),
+        None => Err(format!("bad string {text}")),
+    }
+}
+
+fn parse_directive(name: &str, operands: &[&str]) -> Result<Statement, String>
+{
+    let count = |text: &str| match parse_expression(text)?.constant()
+    {
+        Some(value) if (0..=u32::MAX as i64).contains(&value) => Ok(value as u32),
+        _ => Err(format!("{name} needs a constant, not {text}")),
+    };
+
+    match (name, operands)
+    {
+        (".text", []) => Ok(Statement::Section(Section::Text)),
+        (".data", []) => Ok(Statement::Section(Section::Data)),
+        (".word" | ".half" | ".byte", values) if !values.is_empty() =>
+        {
+            let width = match name
+            {
+                ".word" => 4,
+                ".half" => 2,
+                _ => 1,
+            };
+            let values: Result<Vec<Expression>, String> = values.iter().map(|value| parse_expression(value)).collect();
+            Ok(Statement::Data(width, values?))
+        },
+        (".ascii" | ".asciiz", strings) if !strings.is_empty() =>
+        {
+            let mut bytes = Vec::new();
+            for string in strings
+            {
+                bytes.extend(parse_string(string)?);
+                if name == ".asciiz"
+                {
+                    bytes.push(0);
+                }
+            }
+            Ok(Statement::Bytes(bytes))
+        },
+        (".align", [power]) =>
+        {
+            let power = count(power)?;
+            if power > 12
+            {
+                return Err(format!("bad alignment {power}"));
+            }
+            Ok(Statement::Align(power))
+        },
+        (".space", [size]) => Ok(Statement::Space(count(size)?)),
+        (".globl" | ".global", [symbol]) if is_identifier(symbol) => Ok(Statement::Global(symbol.to_string())),
+        (".equ" | ".set", [symbol, value]) if is_identifier(symbol) =>
+            Ok(Statement::Equ(symbol.to_string(), parse_expression(value)?)),
+        (".text" | ".data" | ".word" | ".half" | ".byte" | ".ascii" | ".asciiz" | ".align" | ".space" | ".globl"
+        | ".global" | ".equ" | ".set", _) => Err(format!("bad operands for {name}")),
+        _ => Err(format!("unknown directive {name}")),
+    }
+}
+
+pub(crate) fn parse_line(number: usize, line: &str) -> Result<Line, String>
+{
+    let mut rest = strip_comment(line).trim();
+
+    let mut labels = Vec::new();
+    while let Some(colon) = rest.find(':')
+    {
+        let label = rest[..colon].trim();
+        if !is_identifier(label)
+        {
+            break;
+        }
+        labels.push(label.to_string());
+        rest = rest[colon + 1..].trim();
+    }
+
+    if rest.is_empty()
+    {
+        return Ok(Line { number, labels, statement: None });
+    }
+
+    let (name, operands) = match rest.find(char::is_whitespace)
+    {
+        Some(space) => (&rest[..space], rest[space..].trim()),
+        None => (rest, ""),
+    };
+    let operands = split_operands(operands);
+    if operands.iter().any(|operand| operand.is_empty())
+    {
+        return Err("missing operand".to_string());
+    }
+
+    let statement = if name.starts_with('.')
+    {
+        parse_directive(name, &operands)?
+    }
+    else
+    {
+        let operands: Result<Vec<Operand>, String> = operands.iter().map(|operand| parse_operand(operand)).collect();
+        Statement::Instruction(name.to_lowercase(), operands?)
+    };
+    Ok(Line { number, labels, statement: Some(statement) })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn statement(line: &str) -> Statement
+    {
+        parse_line(1, line).unwrap().statement.unwrap()
+    }
+
+    #[test]
+    fn labels_and_comments_come_off_the_line()
+    {
+        let line = parse_line(7, "start: loop:  # nothing else").unwrap();
+        assert_eq!(line.number, 7);
+        assert_eq!(line.labels, ["start", "loop"]);
+        assert!(line.statement.is_none());
+
+        let line = parse_line(1, "main: .asciiz \"a#b\", \"c,d\" # text").unwrap();
+        assert_eq!(line.labels, ["main"]);
+        assert!(matches!(line.statement, Some(Statement::Bytes(bytes)) if bytes == b"a#b\0c,d\0"));
+
+        // a colon in an operand is no label
+        assert!(matches!(statement("li $t0, ':'"), Statement::Instruction(..)));
+        assert!(parse_line(1, "  ").unwrap().statement.is_none());
+    }
+
+    #[test]
+    fn operands_are_registers_expressions_or_memory()
+    {
+        let Statement::Instruction(mnemonic, operands) = statement("LW $ra, %lo(table+8)($sp)")
+        else { panic!("Bad statement") };
+        assert_eq!(mnemonic, "lw");
+        match operands.as_slice()
+        {
+            [Operand::Register(31), Operand::Memory(offset, 29)] =>
+            {
+                assert_eq!(offset.part, Part::Lo);
+                assert!(matches!(offset.terms.as_slice(),
+                                 [(false, Term::Symbol(name)), (false, Term::Number(8))] if name == "table"));
+            },
+            operands => panic!("Bad operands {operands:?}"),
+        }
+
+        let Statement::Instruction(_, operands) = statement("addiu $s8, $8, -'a' + end - 0x10")
+        else { panic!("Bad statement") };
+        match operands.as_slice()
+        {
+            [Operand::Register(30), Operand::Register(8), Operand::Expression(value)] =>
+            {
+                assert!(matches!(value.terms.as_slice(), [(true, Term::Number(97)), (false, Term::Symbol(name)),
+                                                          (true, Term::Number(16))] if name == "end"));
+                assert_eq!(value.constant(), None);
+            },
+            operands => panic!("Bad operands {operands:?}"),
+        }
+
+        let Statement::Instruction(_, operands) = statement("sw $t0, ($t1)") else { panic!("Bad statement") };
+        assert!(matches!(operands.as_slice(), [_, Operand::Memory(offset, 9)] if offset.constant() == Some(0)));
+    }
+
+    #[test]
+    fn directives_parse_their_operands()
+    {
+        assert!(matches!(statement(".data"), Statement::Section(Section::Data)));
+        assert!(matches!(statement(".half 1, -2, 0b11"), Statement::Data(2, values)
+                         if values.iter().map(|value| value.constant()).eq([Some(1), Some(-2), Some(3)])));
+        assert!(matches!(statement(".align 3"), Statement::Align(3)));
+        assert!(matches!(statement(".space 0x20"), Statement::Space(32)));
+        assert!(matches!(statement(".globl main"), Statement::Global(name) if name == "main"));
+        assert!(matches!(statement(".set SIZE, 4 + 2"), Statement::Equ(name, value)
+                         if name == "SIZE" && value.constant() == Some(6)));
+    }
+
+    #[test]
+    fn bad_lines_say_what_is_wrong()
+    {
+        let error = |line: &str| parse_line(1, line).err().unwrap_or_default();
+        assert_eq!(error("addu $t0, $t9x, $t1"), "bad register $t9x");
+        assert_eq!(error("addu $t0, $32, $t1"), "bad register $32");
+        assert_eq!(error("addu $t0, , $t1"), "missing operand");
+        assert_eq!(error("li $t0, 1 +"), "bad expression 1 +");
+        assert_eq!(error("li $t0, 12ab"), "bad number 12ab");
+        assert_eq!(error("li $t0, 'ab'"), "bad character 'ab'");
+        assert_eq!(error(".equ SIZE, 4 * 2"), "bad number 4 * 2"); // sums only
+        assert_eq!(error(".ascii \"\\q\""), "bad escape \\q");
+        assert_eq!(error(".align 13"), "bad alignment 13");
+        assert_eq!(error(".space size"), ".space needs a constant, not size");
+        assert_eq!(error(".word"), "bad operands for .word");
+        assert_eq!(error(".bss"), "unknown directive .bss");
+    }
+}
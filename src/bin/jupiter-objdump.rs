@@ -0,0 +1,107 @@
+// disassembles a raw big-endian ROM or program image
+
+use std::io::{self, BufWriter, Write};
+
+use std::path::Path;
+
+use computer::{disassemble_with_symbols, Symbols};
+
+const USAGE: &str = "image filename [base=ADDR] [start=ADDR] [count=N] [symbols=FILE]...
+base is the address the image is loaded at, start and count limit the listing; numbers are decimal or 0x hex
+symbols is an ELF file or a map of \"address name\" lines, its names label the listing and the jump targets";
+
+fn parse_number(text: &str) -> Option<u32>
+{
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn usage() -> !
+{
+    eprintln!("{USAGE}");
+    std::process::exit(1);
+}
+
+fn main()
+{
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2
+    {
+        usage();
+    }
+
+    let (mut base, mut start, mut count, mut symbols) = (0, None, None, Symbols::default());
+    for arg in &args[2..]
+    {
+        let (key, value) = arg.split_once('=').unwrap_or_else(|| usage());
+        if key == "symbols"
+        {
+            match Symbols::load(Path::new(value))
+            {
+                Ok(loaded) => symbols = symbols.merge(&loaded),
+                Err(error) =>
+                {
+                    eprintln!("Bad symbol file {value}: {error}");
+                    std::process::exit(1);
+                },
+            }
+            continue;
+        }
+        let value = parse_number(value).unwrap_or_else(|| usage());
+        match key
+        {
+            "base" => base = value,
+            "start" => start = Some(value),
+            "count" => count = Some(value),
+            _ => usage(),
+        }
+    }
+
+    let image = match std::fs::read(&args[1])
+    {
+        Ok(image) => image,
+        Err(error) =>
+        {
+            eprintln!("Cannot read {}: {error}", args[1]);
+            std::process::exit(1);
+        },
+    };
+
+    let start = start.unwrap_or(base) & !3;
+    let first = start.saturating_sub(base) as usize;
+    let mut out = BufWriter::new(io::stdout().lock());
+    for (i, bytes) in image.get(first..).unwrap_or(&[]).chunks(4).enumerate()
+    {
+        if count.is_some_and(|count| i as u32 >= count)
+        {
+            break;
+        }
+
+        let address = start.wrapping_add(4 * i as u32);
+        let label = match symbols.name_at(address)
+        {
+            Some(name) => format!("\n{address:08x} <{name}>:\n"),
+            None => String::new(),
+        };
+        let line = match *bytes
+        {
+            [a, b, c, d] =>
+            {
+                let word = u32::from_be_bytes([a, b, c, d]);
+                format!("{label}{address:08x}: {word:08x}  {}", disassemble_with_symbols(word, address, &symbols))
+            },
+            _ =>
+            {
+                let tail: Vec<String> = bytes.iter().map(|byte| format!("{byte:#04x}")).collect();
+                format!("{label}{address:08x}: {:<8}  .byte {}", "", tail.join(", "))
+            },
+        };
+        if writeln!(out, "{line}").is_err()
+        {
+            return; // closed pipe
+        }
+    }
+}
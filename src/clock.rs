@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use computer::Computer;
+use computer_config::Config;
+
+const TURBO_BATCH: u32 = 1000; // cycles between deadline checks in turbo mode
+const MAX_FRAMES_BEHIND: f64 = 4.0; // real-time lag we try to catch up on
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Speed
+{
+    RealTime,
+    Turbo,
+    Paused,
+}
+
+pub(crate) struct Clock
+{
+    frequency: Option<u64>,
+    cycles_per_frame: u32,
+    frame_duration: Duration,
+    speed: Speed,
+
+    // point at which real-time emulation was (re)started
+    base_time: Instant,
+    base_cycles: u64,
+}
+
+impl Clock
+{
+    pub(crate) fn new(config: &Config) -> Self
+    {
+        Clock
+        {
+            frequency: config.clock_frequency(),
+            cycles_per_frame: config.cycles_per_frame(),
+            frame_duration: Duration::from_secs_f64(1.0 / config.frame_rate() as f64),
+            speed: Speed::RealTime,
+            base_time: Instant::now(),
+            base_cycles: 0,
+        }
+    }
+
+    pub(crate) fn frame_duration(&self) -> Duration
+    {
+        self.frame_duration
+    }
+
+    pub(crate) fn speed(&self) -> Speed
+    {
+        self.speed
+    }
+
+    pub(crate) fn set_speed(&mut self, speed: Speed, computer: &Computer)
+    {
+        self.speed = speed;
+        self.rebase(computer.cycles());
+    }
+
+    fn rebase(&mut self, cycles: u64)
+    {
+        self.base_time = Instant::now();
+        self.base_cycles = cycles;
+    }
+
+    // runs the cycles due for one frame, returns how many were run
+    pub(crate) fn run_frame(&mut self, computer: &mut Computer) -> u64
+    {
+        let start = computer.cycles();
+        if computer.halted()
+        {
+            self.rebase(start); // stopped in the monitor, emulated time stands still
+            return 0;
+        }
+
+        match self.speed
+        {
+            Speed::Paused => {},
+            Speed::Turbo =>
+            {
+                let deadline = Instant::now() + self.frame_duration;
+                while Instant::now() < deadline
+                {
+                    for _ in 0..TURBO_BATCH
+                    {
+                        computer.cycle();
+                    }
+                }
+            },
+            Speed::RealTime => match self.frequency
+            {
+                None =>
+                {
+                    for _ in 0..self.cycles_per_frame
+                    {
+                        computer.cycle();
+                    }
+                },
+                Some(frequency) =>
+                {
+                    let due = (self.base_time.elapsed().as_secs_f64() * frequency as f64) as u64;
+                    let done = start - self.base_cycles;
+                    let mut to_run = due.saturating_sub(done);
+
+                    let max = (frequency as f64 * self.frame_duration.as_secs_f64() * MAX_FRAMES_BEHIND) as u64;
+                    let behind = to_run > max;
+                    if behind
+                    {
+                        to_run = max;
+                    }
+
+                    for _ in 0..to_run
+                    {
+                        computer.cycle();
+                    }
+
+                    if behind
+                    {
+                        self.rebase(computer.cycles()); // host can't keep up, drop the lost time
+                    }
+                },
+            },
+        }
+
+        computer.cycles() - start
+    }
+}
@@ -0,0 +1,48 @@
+use computer::Computer;
+use computer_config::Config;
+
+use crate::gdb::GdbStub;
+use crate::monitor::Monitor;
+
+// whichever debugger front the config asked for
+pub(crate) enum Debugger
+{
+    Monitor(Monitor),
+    Gdb(GdbStub),
+}
+
+impl Debugger
+{
+    pub(crate) fn new(config: &Config, computer: &mut Computer) -> Option<Debugger>
+    {
+        if let Some(port) = config.gdb_port()
+        {
+            return Some(Debugger::Gdb(GdbStub::new(port, computer)));
+        }
+        if config.monitor()
+        {
+            return Some(Debugger::Monitor(Monitor::new(computer)));
+        }
+        None
+    }
+
+    // called regularly while the machine runs, false to quit
+    pub(crate) fn poll(&mut self, computer: &mut Computer) -> bool
+    {
+        match self
+        {
+            Debugger::Monitor(monitor) => monitor.poll(computer),
+            Debugger::Gdb(gdb) => gdb.poll(computer),
+        }
+    }
+
+    // blocks while the machine is halted, false to quit
+    pub(crate) fn wait(&mut self, computer: &mut Computer) -> bool
+    {
+        match self
+        {
+            Debugger::Monitor(monitor) => monitor.wait(computer),
+            Debugger::Gdb(gdb) => gdb.wait(computer),
+        }
+    }
+}
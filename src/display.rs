@@ -1,39 +1,118 @@
-use computer::Computer;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use winit::dpi::PhysicalSize;
-use winit::event::{Event, WindowEvent};
+use computer::{Computer, MouseButton, Screen};
+use computer_config::Config;
+
+use crate::audio::AudioOutput;
+use crate::clock::{Clock, Speed};
+use crate::debugger::Debugger;
+use crate::dump::dump_frame;
+use crate::recorder::{record_frame, start_recording, stop_recording, Recorder};
+use crate::scaler::Scaler;
+
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, Window, WindowBuilder};
 use pixels::{Pixels, SurfaceTexture};
 
-fn draw(frame: &mut [u8], vram: Vec<u8>)
+const TITLE: &str = "Super emulator kurwo";
+
+fn draw(frame: &mut [u8], screen: &Screen, rows: (u32, u32))
 {
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate()
+    let (from, to) = rows;
+    let frame_stride = (screen.width() * 4) as usize;
+
+    for y in from..to
     {
+        let start = y as usize * frame_stride;
+        screen.row_to_rgba(y, &mut frame[start..start + frame_stride]);
+    }
+}
 
-        let address = 3 * i;
-        let r = vram[address];
-        let g = vram[address + 1];
-        let b = vram[address + 2];
+// wheel holds the fractions of a line scrolled but not passed on yet
+fn mouse_event(computer: &mut Computer, scaler: &Scaler, wheel: &mut (f32, f32), event: &WindowEvent)
+{
+    match event
+    {
+        WindowEvent::CursorMoved { position, .. } =>
+        {
+            let (x, y) = scaler.window_pos_to_pixel((position.x, position.y));
+            computer.mouse_moved(x, y);
+        },
+        WindowEvent::CursorEntered { .. } => computer.mouse_inside(true),
+        WindowEvent::CursorLeft { .. } => computer.mouse_inside(false),
+        WindowEvent::MouseInput { state, button, .. } =>
+        {
+            let button = match button
+            {
+                winit::event::MouseButton::Left => MouseButton::Left,
+                winit::event::MouseButton::Right => MouseButton::Right,
+                winit::event::MouseButton::Middle => MouseButton::Middle,
+                winit::event::MouseButton::Other(_) => return,
+            };
+            computer.mouse_button(button, *state == ElementState::Pressed);
+        },
+        WindowEvent::MouseWheel { delta, .. } =>
+        {
+            let (dx, dy) = match delta
+            {
+                MouseScrollDelta::LineDelta(x, y) =>
+                {
+                    // trackpads scroll by fractions of a line, the guest only gets whole steps
+                    let (x, y) = (wheel.0 + x, wheel.1 + y);
+                    *wheel = (x.fract(), y.fract());
+                    (x.trunc() as i32, y.trunc() as i32)
+                },
+                MouseScrollDelta::PixelDelta(pos) => (pos.x.signum() as i32, pos.y.signum() as i32),
+            };
+            computer.mouse_wheel(dx, dy);
+        },
+        _ => {},
+    }
+}
 
-        let rgba = [r, g, b, 255];
+fn toggle_fullscreen(window: &Window)
+{
+    let fullscreen = match window.fullscreen()
+    {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(None)),
+    };
+    window.set_fullscreen(fullscreen);
+}
 
-        pixel.copy_from_slice(&rgba);
-    }
+// the event loop never returns, nothing gets dropped, so everything is finished by hand
+fn shutdown(computer: &mut Computer, recorder: &mut Option<Recorder>, audio: &mut AudioOutput,
+            control_flow: &mut ControlFlow)
+{
+    stop_recording(recorder);
+    audio.finish();
+    computer.write_reports();
+    *control_flow = ControlFlow::Exit;
 }
 
-pub(crate) fn display(mut computer: Computer, width: u32, height: u32)
+pub(crate) fn display(mut computer: Computer, config: &Config)
 {
+    let width = config.width();
+    let height = config.height();
+    let mut clock = Clock::new(config);
+    let dump_dir = config.dump_dir().clone();
+
     let event_loop = EventLoop::new();
 
-    let size = PhysicalSize
+    // logical size, so the window is not tiny on HiDPI screens
+    let size = LogicalSize
     {
-        width,
-        height,
+        width: width * config.scale(),
+        height: height * config.scale(),
     };
+    let fullscreen = if config.fullscreen() { Some(Fullscreen::Borderless(None)) } else { None };
     let window = WindowBuilder::new()
-        .with_title("Super emulator kurwo")
+        .with_title(TITLE)
         .with_inner_size(size)
+        .with_fullscreen(fullscreen)
         .build(&event_loop)
         .unwrap();
 
@@ -41,31 +120,189 @@ pub(crate) fn display(mut computer: Computer, width: u32, height: u32)
     let window_size = window.inner_size();
     let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
     let mut pixels = Pixels::new(width, height, surface_texture).unwrap();
+    let mut scaler = Scaler::new(&pixels, config.scaling(), config.filter(), (width, height),
+                                 (window_size.width, window_size.height));
+
+    let frame_rate = config.frame_rate();
+    let mut recorder = config.record().as_ref()
+        .and_then(|path| start_recording(Path::new(path), &computer, frame_rate));
+    let mut audio = AudioOutput::new(config);
+    let mut debugger = Debugger::new(config, &mut computer);
+
+    let mut next_frame = Instant::now();
+    let mut wheel = (0.0, 0.0);
+
+    // instructions per second measurement
+    let mut ips_start = Instant::now();
+    let mut ips_cycles: u64 = 0;
 
     event_loop.run(move |event, _, control_flow|
         {
-            *control_flow = ControlFlow::Wait;
+            *control_flow = ControlFlow::WaitUntil(next_frame);
 
-            if let Event::WindowEvent
+            match event
             {
-                event: WindowEvent::CloseRequested,
-                ..
-            } = event
-            {
-                *control_flow = ControlFlow::Exit;
-            }
+                Event::WindowEvent { event, .. } => match event
+                {
+                    WindowEvent::CloseRequested => shutdown(&mut computer, &mut recorder, &mut audio, control_flow),
+                    WindowEvent::KeyboardInput { input, .. } =>
+                    {
+                        let pressed = input.state == ElementState::Pressed;
 
-            if let Event::RedrawRequested(_) = event
-            {
-                if pixels.render().is_err()
+                        // emulator hotkeys are not passed to the guest
+                        if input.virtual_keycode == Some(VirtualKeyCode::F11)
+                        {
+                            if pressed
+                            {
+                                toggle_fullscreen(&window);
+                            }
+                            return;
+                        }
+                        if input.virtual_keycode == Some(VirtualKeyCode::F7)
+                        {
+                            if pressed && debugger.is_some()
+                            {
+                                computer.halt(); // break into the debugger
+                            }
+                            return;
+                        }
+                        if input.virtual_keycode == Some(VirtualKeyCode::F8)
+                        {
+                            if pressed
+                            {
+                                if recorder.is_some()
+                                {
+                                    stop_recording(&mut recorder);
+                                }
+                                else
+                                {
+                                    let name = format!("recording_{:012}.y4m", computer.cycles());
+                                    let path = Path::new(&dump_dir).join(name);
+                                    recorder = start_recording(&path, &computer, frame_rate);
+                                }
+                            }
+                            return;
+                        }
+                        if input.virtual_keycode == Some(VirtualKeyCode::F12)
+                        {
+                            if pressed
+                            {
+                                dump_frame(&computer, &dump_dir, "screenshot");
+                            }
+                            return;
+                        }
+
+                        let speed = match input.virtual_keycode
+                        {
+                            Some(VirtualKeyCode::F9) => Some(Speed::Paused),
+                            Some(VirtualKeyCode::F10) => Some(Speed::Turbo),
+                            _ => None,
+                        };
+
+                        match speed
+                        {
+                            Some(speed) if pressed =>
+                            {
+                                let speed = if clock.speed() == speed { Speed::RealTime } else { speed };
+                                clock.set_speed(speed, &computer);
+                            },
+                            Some(_) => {},
+                            None => computer.key_event(input.scancode, pressed),
+                        }
+                    },
+                    WindowEvent::ReceivedCharacter(c) => computer.char_event(c),
+                    WindowEvent::Resized(size) =>
+                    {
+                        pixels.resize_surface(size.width, size.height);
+                        scaler.resize(size.width, size.height);
+                        window.request_redraw();
+                    },
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
+                    {
+                        pixels.resize_surface(new_inner_size.width, new_inner_size.height);
+                        scaler.resize(new_inner_size.width, new_inner_size.height);
+                        window.request_redraw();
+                    },
+                    event => mouse_event(&mut computer, &scaler, &mut wheel, &event),
+                },
+
+                Event::MainEventsCleared =>
                 {
-                    *control_flow = ControlFlow::Exit;
-                    return;
-                }
-            }
+                    let now = Instant::now();
+                    if now < next_frame
+                    {
+                        return;
+                    }
 
-            computer.cycle();
-            let vram = computer.get_vram();
-            draw(pixels.get_frame(), vram);
+                    if let Some(debugger) = &mut debugger
+                    {
+                        if !debugger.poll(&mut computer)
+                        {
+                            shutdown(&mut computer, &mut recorder, &mut audio, control_flow);
+                            return;
+                        }
+                    }
+
+                    ips_cycles += clock.run_frame(&mut computer);
+                    // no frames pass while stopped in the debugger or paused
+                    if !computer.halted() && clock.speed() != Speed::Paused
+                    {
+                        computer.vblank();
+                        record_frame(&mut recorder, &computer);
+                        audio.update(&mut computer);
+                        computer.flush_trace();
+                    }
+
+                    if computer.take_screenshot_request()
+                    {
+                        dump_frame(&computer, &dump_dir, "screenshot");
+                    }
+
+                    if let Some(rows) = computer.take_dirty_rows()
+                    {
+                        draw(pixels.get_frame(), &computer.screen(), rows);
+                        window.request_redraw();
+                    }
+
+                    next_frame += clock.frame_duration();
+                    if next_frame < now
+                    {
+                        next_frame = now + clock.frame_duration(); // too slow to keep up, don't try to catch up
+                    }
+                    *control_flow = ControlFlow::WaitUntil(next_frame);
+
+                    let elapsed = ips_start.elapsed();
+                    if elapsed >= Duration::from_secs(1)
+                    {
+                        let ips = ips_cycles as f64 / elapsed.as_secs_f64();
+                        let speed = match clock.speed()
+                        {
+                            Speed::RealTime => "",
+                            Speed::Turbo => " (turbo)",
+                            Speed::Paused => " (paused)",
+                        };
+                        let recording = if recorder.is_some() { " (recording)" } else { "" };
+                        window.set_title(&format!("{TITLE} - {ips:.0} IPS{speed}{recording}"));
+
+                        ips_start = Instant::now();
+                        ips_cycles = 0;
+                    }
+                },
+
+                Event::RedrawRequested(_) =>
+                {
+                    let result = pixels.render_with(|encoder, target, _|
+                        {
+                            scaler.render(encoder, target);
+                            Ok(())
+                        });
+                    if result.is_err()
+                    {
+                        shutdown(&mut computer, &mut recorder, &mut audio, control_flow);
+                    }
+                },
+
+                _ => {},
+            }
         });
-}
\ No newline at end of file
+}
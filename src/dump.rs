@@ -0,0 +1,13 @@
+use std::path::Path;
+
+use computer::Computer;
+
+// saves the current picture as <dir>/<prefix>_<cycle>.ppm
+pub(crate) fn dump_frame(computer: &Computer, dir: &str, prefix: &str)
+{
+    let path = Path::new(dir).join(format!("{prefix}_{:012}.ppm", computer.cycles()));
+    if let Err(error) = computer.save_screenshot(&path)
+    {
+        eprintln!("Cannot save {}: {error}", path.display());
+    }
+}
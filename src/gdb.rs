@@ -0,0 +1,559 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use computer::{Computer, WatchAction, WatchKind};
+
+// gdb's mips numbering: 0-31 general purpose, 32 status, 33 lo, 34 hi, 35 badvaddr, 36 cause, 37 pc,
+// 38-69 floating point, 70 fcsr, 71 fir
+const REGISTER_COUNT: usize = 72;
+const STATUS: usize = 32;
+const LO: usize = 33;
+const HI: usize = 34;
+const CAUSE: usize = 36;
+const PC: usize = 37;
+
+enum Reply
+{
+    Send(String),
+    Resume, // reply comes with the next stop
+    Detach,
+    Kill,
+}
+
+// remote serial protocol server on localhost
+pub(crate) struct GdbStub
+{
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    no_ack: bool,
+    running: bool, // continued by gdb, owes it a stop reply
+}
+
+impl GdbStub
+{
+    // waits for gdb to connect, the machine stays halted until it says continue
+    pub(crate) fn new(port: u16, computer: &mut Computer) -> GdbStub
+    {
+        let listener = match TcpListener::bind(("127.0.0.1", port))
+        {
+            Ok(listener) => listener,
+            Err(error) => panic!("Cannot listen for gdb on port {port}: {error}"),
+        };
+
+        let mut stub = GdbStub
+        {
+            listener,
+            stream: None,
+            buffer: Vec::new(),
+            no_ack: false,
+            running: false,
+        };
+        stub.accept(computer, true);
+        stub
+    }
+
+    fn accept(&mut self, computer: &mut Computer, blocking: bool)
+    {
+        if blocking
+        {
+            println!("waiting for gdb on 127.0.0.1:{}", self.listener.local_addr().unwrap().port());
+        }
+
+        let _ = self.listener.set_nonblocking(!blocking);
+        if let Ok((stream, _)) = self.listener.accept()
+        {
+            let _ = stream.set_nodelay(true);
+            self.stream = Some(stream);
+            self.buffer.clear();
+            self.no_ack = false;
+            self.running = false;
+            computer.halt();
+        }
+    }
+
+    // serves gdb without blocking, false when gdb kills the machine
+    pub(crate) fn poll(&mut self, computer: &mut Computer) -> bool
+    {
+        if self.stream.is_none()
+        {
+            self.accept(computer, false);
+            return true;
+        }
+        self.serve(computer, false)
+    }
+
+    // serves gdb while the machine is halted, false when gdb kills it
+    pub(crate) fn wait(&mut self, computer: &mut Computer) -> bool
+    {
+        while computer.halted()
+        {
+            if self.stream.is_none()
+            {
+                computer.resume(); // nobody attached, carry on
+                return true;
+            }
+            if !self.serve(computer, true)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn serve(&mut self, computer: &mut Computer, blocking: bool) -> bool
+    {
+        self.stop_reply(computer);
+
+        let stream = self.stream.as_mut().unwrap();
+        let _ = stream.set_nonblocking(!blocking);
+        let mut chunk = [0u8; 4096];
+        match stream.read(&mut chunk)
+        {
+            Ok(0) => return self.disconnect(computer),
+            Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => return true,
+            Err(_) => return self.disconnect(computer),
+        }
+
+        while let Some(packet) = self.next_packet(computer)
+        {
+            match self.handle(computer, &packet)
+            {
+                Reply::Send(reply) => self.send(&reply),
+                Reply::Resume => self.running = true,
+                Reply::Detach =>
+                {
+                    self.send("OK");
+                    return self.disconnect(computer);
+                },
+                Reply::Kill => return false,
+            }
+            if packet == b"QStartNoAckMode"
+            {
+                self.no_ack = true; // from the next packet on
+            }
+        }
+
+        self.stop_reply(computer);
+        true
+    }
+
+    fn disconnect(&mut self, computer: &mut Computer) -> bool
+    {
+        self.stream = None;
+        self.running = false;
+        computer.resume();
+        true
+    }
+
+    // after a continue, tells gdb once the machine has stopped
+    fn stop_reply(&mut self, computer: &mut Computer)
+    {
+        if self.running && computer.halted()
+        {
+            self.running = false;
+            let reply = Self::stop_reason(computer);
+            self.send(&reply);
+        }
+    }
+
+    fn stop_reason(computer: &mut Computer) -> String
+    {
+        match computer.take_watch_hit()
+        {
+            Some((WatchKind::Write, address)) => format!("T05watch:{address:x};"),
+            Some((WatchKind::Read, address)) => format!("T05rwatch:{address:x};"),
+            Some((WatchKind::Access, address)) => format!("T05awatch:{address:x};"),
+            Some((WatchKind::Execute, _)) | None => "S05".to_string(),
+        }
+    }
+
+    // takes the next whole packet off the buffer, handles acks and interrupts on the way
+    fn next_packet(&mut self, computer: &mut Computer) -> Option<Vec<u8>>
+    {
+        loop
+        {
+            match self.buffer.first()
+            {
+                None => return None,
+                Some(b'$') => break,
+                Some(0x03) => computer.halt(), // ctrl-c, the stop reply follows
+                Some(_) => {}, // acks and noise
+            }
+            self.buffer.remove(0);
+        }
+
+        let hash = self.buffer.iter().position(|&byte| byte == b'#')?;
+        if self.buffer.len() < hash + 3
+        {
+            return None; // checksum not here yet
+        }
+
+        let data: Vec<u8> = self.buffer[1..hash].to_vec();
+        let received = std::str::from_utf8(&self.buffer[hash + 1..hash + 3]).ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok());
+        self.buffer.drain(..hash + 3);
+
+        let sum = checksum(&data);
+        if !self.no_ack
+        {
+            let ack: &[u8] = if received == Some(sum) { b"+" } else { b"-" };
+            self.write(ack);
+        }
+        if received != Some(sum)
+        {
+            return self.next_packet(computer);
+        }
+
+        Some(data)
+    }
+
+    fn send(&mut self, reply: &str)
+    {
+        let sum = checksum(reply.as_bytes());
+        let packet = format!("${reply}#{sum:02x}");
+        self.write(packet.as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        if let Some(stream) = &mut self.stream
+        {
+            let _ = stream.set_nonblocking(false);
+            if stream.write_all(bytes).is_err()
+            {
+                self.stream = None;
+            }
+        }
+    }
+}
+
+impl GdbStub // packets
+{
+    // packets are bytes off the wire, anything that isn't ASCII is just a bad argument
+    fn handle(&mut self, computer: &mut Computer, packet: &[u8]) -> Reply
+    {
+        let (command, args) = packet.split_first().map_or((0, &[][..]), |(&command, args)| (command, args));
+        let args = &String::from_utf8_lossy(args);
+        let reply = match command
+        {
+            b'?' => Self::stop_reason(computer),
+            b'g' => Self::read_registers(computer),
+            b'G' => Self::write_registers(computer, args),
+            b'p' => match usize::from_str_radix(args, 16)
+            {
+                Ok(number) => Self::read_register(computer, number),
+                Err(_) => "E01".to_string(),
+            },
+            b'P' => Self::write_register_packet(computer, args),
+            b'm' => Self::read_memory(computer, args),
+            b'M' => Self::write_memory(computer, args),
+            b's' => Self::step(computer, args),
+            b'c' =>
+            {
+                if let Some(address) = parse_hex(args)
+                {
+                    computer.set_register(32, address as i32);
+                }
+                computer.resume();
+                return Reply::Resume;
+            },
+            b'Z' | b'z' => Self::breakpoint(computer, command == b'Z', args),
+            b'H' => "OK".to_string(),
+            b'k' => return Reply::Kill,
+            b'D' => return Reply::Detach,
+            b'q' | b'Q' | b'v' => Self::query(&String::from_utf8_lossy(packet)),
+            _ => String::new(),
+        };
+        Reply::Send(reply)
+    }
+
+    fn query(packet: &str) -> String
+    {
+        match packet
+        {
+            _ if packet.starts_with("qSupported") => "PacketSize=4000;QStartNoAckMode+".to_string(),
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn register_value(computer: &Computer, number: usize) -> Option<u32>
+    {
+        let (registers, float_registers) = computer.registers();
+        match number
+        {
+            0..=31 => Some(registers[number] as u32),
+            STATUS => Some(computer.status()),
+            LO => Some(registers[34] as u32),
+            HI => Some(registers[33] as u32),
+            CAUSE => Some(computer.cause()),
+            PC => Some(registers[32] as u32),
+            38..=69 => Some(float_registers[number - 38].to_bits()),
+            _ => None, // badvaddr, fcsr, fir
+        }
+    }
+
+    fn read_register(computer: &Computer, number: usize) -> String
+    {
+        match Self::register_value(computer, number)
+        {
+            Some(value) => format!("{value:08x}"),
+            None if number < REGISTER_COUNT => "xxxxxxxx".to_string(),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn read_registers(computer: &Computer) -> String
+    {
+        (0..REGISTER_COUNT).map(|number| Self::read_register(computer, number)).collect()
+    }
+
+    fn write_register(computer: &mut Computer, number: usize, value: u32) -> bool
+    {
+        match number
+        {
+            0..=31 => computer.set_register(number, value as i32),
+            LO => computer.set_register(34, value as i32),
+            HI => computer.set_register(33, value as i32),
+            PC => computer.set_register(32, value as i32),
+            STATUS =>
+            {
+                computer.set_status(value);
+                true
+            },
+            _ => true, // read only or not emulated, ignored
+        }
+    }
+
+    fn write_registers(computer: &mut Computer, args: &str) -> String
+    {
+        let values = match hex_fields(args, 8)
+        {
+            Some(values) => values,
+            None => return "E01".to_string(),
+        };
+
+        for (number, value) in values.into_iter().enumerate()
+        {
+            if Self::register_value(computer, number) != Some(value) && !Self::write_register(computer, number, value)
+            {
+                return "E01".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn write_register_packet(computer: &mut Computer, args: &str) -> String
+    {
+        let parsed = args.split_once('=')
+            .and_then(|(number, value)| Some((usize::from_str_radix(number, 16).ok()?, parse_hex(value)?)));
+        match parsed
+        {
+            Some((number, value)) if Self::write_register(computer, number, value) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(computer: &Computer, args: &str) -> String
+    {
+        let range = args.split_once(',').and_then(|(address, len)| Some((parse_hex(address)?, parse_hex(len)?)));
+        let bytes = range.and_then(|(address, len)| computer.read_memory(address, len));
+        match bytes
+        {
+            Some(bytes) => bytes.iter().map(|byte| format!("{byte:02x}")).collect(),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(computer: &mut Computer, args: &str) -> String
+    {
+        let parsed = args.split_once(':').and_then(|(range, data)|
+        {
+            let (address, len) = range.split_once(',')?;
+            let bytes = hex_fields(data, 2)?.into_iter().map(|byte| byte as u8).collect::<Vec<u8>>();
+            Some((parse_hex(address)?, parse_hex(len)?, bytes))
+        });
+
+        match parsed
+        {
+            Some((address, len, bytes)) if bytes.len() == len as usize && computer.write_memory(address, &bytes) =>
+                "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn step(computer: &mut Computer, args: &str) -> String
+    {
+        if let Some(address) = parse_hex(args)
+        {
+            computer.set_register(32, address as i32);
+        }
+        computer.step();
+        Self::stop_reason(computer)
+    }
+
+    // Z0/Z1 breakpoints, Z2 write, Z3 read and Z4 access watchpoints
+    fn breakpoint(computer: &mut Computer, insert: bool, args: &str) -> String
+    {
+        let fields: Vec<&str> = args.split(',').collect();
+        let (kind, address, len) = match fields.as_slice()
+        {
+            [kind, address, len, ..] => match (parse_hex(address), parse_hex(len))
+            {
+                (Some(address), Some(len)) => (*kind, address, len),
+                _ => return "E01".to_string(),
+            },
+            _ => return "E01".to_string(),
+        };
+
+        let watch = match kind
+        {
+            "0" | "1" =>
+            {
+                if insert
+                {
+                    computer.add_breakpoint(address);
+                }
+                else
+                {
+                    computer.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            },
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        if insert
+        {
+            computer.add_watchpoint(address, len, watch, WatchAction::Halt);
+        }
+        else
+        {
+            computer.remove_watchpoint(address, len, watch);
+        }
+        "OK".to_string()
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u32>
+{
+    u32::from_str_radix(text, 16).ok()
+}
+
+// fixed width hex numbers run together, a trailing partial one is ignored
+fn hex_fields(text: &str, digits: usize) -> Option<Vec<u32>>
+{
+    text.as_bytes().chunks_exact(digits)
+        .map(|field| std::str::from_utf8(field).ok().and_then(parse_hex))
+        .collect()
+}
+
+// modulo 256 sum of the packet data
+fn checksum(data: &[u8]) -> u8
+{
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::time::Duration;
+
+    use computer_config::Config;
+
+    fn machine() -> Computer
+    {
+        let disk = std::env::temp_dir().join(format!("jupiter-gdb-test-{}.disk", std::process::id()));
+        let args = ["jupiter", "none", "none", &disk.display().to_string(), "4K", "1M", "64", "48"];
+        let computer = Computer::new(Config::from_args(args.iter().map(|arg| arg.to_string()).collect()));
+        let _ = std::fs::remove_file(disk);
+        computer
+    }
+
+    // a stub with gdb's end of the connection
+    fn connected() -> (GdbStub, TcpStream)
+    {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let stub = GdbStub { listener, stream: Some(server), buffer: Vec::new(), no_ack: false, running: false };
+        (stub, client)
+    }
+
+    fn received(client: &mut TcpStream, len: usize) -> String
+    {
+        let mut bytes = vec![0; len];
+        client.read_exact(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn replies_are_framed_with_their_checksum()
+    {
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"OK"), 0x9a);
+        assert_eq!(checksum(&[0xFF, 0x02]), 0x01);
+
+        let (mut stub, mut client) = connected();
+        stub.send("OK");
+        stub.send("");
+        assert_eq!(received(&mut client, 10), "$OK#9a$#00");
+    }
+
+    #[test]
+    fn packets_are_acked_by_checksum_and_wait_until_complete()
+    {
+        let mut computer = machine();
+        let (mut stub, mut client) = connected();
+
+        // acks and noise before the packet are dropped, a packet without its checksum waits
+        stub.buffer.extend_from_slice(b"+-x$g#6");
+        assert_eq!(stub.next_packet(&mut computer), None);
+        stub.buffer.extend_from_slice(b"7$m0,4#fd");
+        assert_eq!(stub.next_packet(&mut computer).as_deref(), Some(&b"g"[..]));
+        assert_eq!(stub.next_packet(&mut computer).as_deref(), Some(&b"m0,4"[..]));
+        assert_eq!(received(&mut client, 2), "++");
+
+        // a bad checksum is nacked and skipped, ctrl-c between packets halts the machine
+        stub.buffer.extend_from_slice(b"$g#00\x03$?#3f");
+        assert_eq!(stub.next_packet(&mut computer).as_deref(), Some(&b"?"[..]));
+        assert!(computer.halted());
+        assert_eq!(received(&mut client, 2), "-+");
+
+        // no acks once gdb asked for QStartNoAckMode
+        stub.no_ack = true;
+        stub.buffer.extend_from_slice(b"$g#67");
+        assert_eq!(stub.next_packet(&mut computer).as_deref(), Some(&b"g"[..]));
+        stub.send("OK");
+        assert_eq!(received(&mut client, 6), "$OK#9a");
+    }
+
+    #[test]
+    fn non_ascii_packets_are_bad_arguments()
+    {
+        let mut computer = machine();
+        let (mut stub, _client) = connected();
+        let reply = |stub: &mut GdbStub, computer: &mut Computer, packet: &[u8]| match stub.handle(computer, packet)
+        {
+            Reply::Send(reply) => reply,
+            _ => panic!("Bad reply"),
+        };
+
+        assert_eq!(reply(&mut stub, &mut computer, "é".as_bytes()), "");
+        assert_eq!(reply(&mut stub, &mut computer, "G0000000é000000".as_bytes()), "E01");
+        assert_eq!(reply(&mut stub, &mut computer, "M0,2:0é".as_bytes()), "E01");
+        assert_eq!(reply(&mut stub, &mut computer, &[b'm', 0xFF, b',', b'4']), "E01");
+        assert!(!reply(&mut stub, &mut computer, b"qSupported:swbreak+").contains("swbreak"));
+    }
+}
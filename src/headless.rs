@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use computer::Computer;
+use computer_config::Config;
+
+use crate::audio::AudioOutput;
+use crate::debugger::Debugger;
+use crate::dump::dump_frame;
+use crate::recorder::{record_frame, start_recording, stop_recording};
+
+// runs without a window, vblank every cycles_per_frame cycles
+pub(crate) fn headless(mut computer: Computer, config: &Config)
+{
+    let cycles_per_frame = config.cycles_per_frame().max(1) as u64;
+    let dump_dir = config.dump_dir();
+    let mut recorder = config.record().as_ref()
+        .and_then(|path| start_recording(Path::new(path), &computer, config.frame_rate()));
+    let mut audio = AudioOutput::new(config);
+    let mut debugger = Debugger::new(config, &mut computer);
+
+    while config.max_cycles().is_none_or(|max| computer.cycles() < max)
+    {
+        if computer.halted()
+        {
+            let resumed = match &mut debugger
+            {
+                Some(debugger) => debugger.wait(&mut computer),
+                None => false,
+            };
+            if !resumed
+            {
+                break;
+            }
+        }
+
+        computer.cycle();
+        let cycles = computer.cycles();
+
+        if cycles.is_multiple_of(cycles_per_frame)
+        {
+            computer.vblank();
+            record_frame(&mut recorder, &computer);
+            audio.update(&mut computer);
+            computer.flush_trace();
+
+            if let Some(debugger) = &mut debugger
+            {
+                if !debugger.poll(&mut computer)
+                {
+                    break;
+                }
+            }
+        }
+
+        let due = match config.dump_every()
+        {
+            Some(every) => cycles.is_multiple_of(every),
+            None => false,
+        };
+        if computer.take_screenshot_request() || due
+        {
+            dump_frame(&computer, dump_dir, "frame");
+        }
+    }
+
+    stop_recording(&mut recorder);
+    audio.update(&mut computer);
+    audio.finish();
+    computer.flush_trace();
+    computer.write_reports();
+}
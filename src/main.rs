@@ -5,16 +5,49 @@ use computer_config::Config;
 
 use computer::*;
 
+mod clock;
 mod display;
+mod scaler;
+mod dump;
+mod headless;
+mod recorder;
+mod audio;
+mod monitor;
+mod gdb;
+mod debugger;
 use display::display;
+use headless::headless;
 
 fn main()
 {
     let args = get_args();
     let config = Config::from_args(args);
-    let width = config.width();
-    let height = config.height();
 
-    let computer = Computer::new(config);
-    display(computer, width, height);
+    let computer = Computer::new(config.clone());
+
+    // a guest crash panics, show where the guest was and what was on the bus before it
+    let executing = computer.executing_pc();
+    let symbols = computer.symbols();
+    let trace = computer.bus_trace();
+    let reports = computer.reports();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info|
+        {
+            default_hook(info);
+            eprintln!("guest pc {}", symbols.describe(executing.get()));
+            if let Some(trace) = &trace
+            {
+                eprintln!("last bus transfers:");
+                let _ = trace.dump(&mut std::io::stderr());
+            }
+            reports.write();
+        }));
+    if config.headless()
+    {
+        headless(computer, &config);
+    }
+    else
+    {
+        display(computer, &config);
+    }
 }
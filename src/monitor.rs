@@ -0,0 +1,362 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use computer::{disassemble_with_symbols, Computer, Symbols, WatchAction, WatchKind, REGISTER_NAMES};
+
+const HELP: &str = "\
+s [n]             step n instructions (default 1)
+c                 continue
+halt              stop the machine
+b ADDR            set breakpoint
+d ADDR            delete breakpoint
+bl                list breakpoints
+r                 show registers
+u [ADDR] [n]      disassemble n instructions (default around pc)
+x ADDR [n]        examine n bytes of memory (default 64)
+w ADDR BYTE...    write bytes to memory
+wp ADDR LEN K [log]  watch LEN bytes, K is r, w, rw or x; log instead of stopping
+wd ADDR LEN K     delete watchpoint
+wl                list watchpoints
+t                 show recent bus transfers (needs trace_buffer)
+q                 quit
+numbers are decimal or 0x hex, addresses can also be symbols, as in main+0x10";
+
+// machine-level monitor reading commands from stdin
+pub(crate) struct Monitor
+{
+    lines: Receiver<String>,
+    stopped: bool, // halt already reported
+}
+
+impl Monitor
+{
+    // the machine starts halted so breakpoints can be set before anything runs
+    pub(crate) fn new(computer: &mut Computer) -> Monitor
+    {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move ||
+            {
+                for line in io::stdin().lock().lines()
+                {
+                    let sent = match line
+                    {
+                        Ok(line) => sender.send(line).is_ok(),
+                        Err(_) => false,
+                    };
+                    if !sent
+                    {
+                        break;
+                    }
+                }
+            });
+
+        computer.halt();
+        Monitor { lines, stopped: false }
+    }
+
+    // runs the commands typed so far, false when the user quits
+    pub(crate) fn poll(&mut self, computer: &mut Computer) -> bool
+    {
+        self.report_halt(computer);
+        loop
+        {
+            match self.lines.try_recv()
+            {
+                Ok(line) =>
+                {
+                    if !self.command(computer, &line)
+                    {
+                        return false;
+                    }
+                },
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) =>
+                {
+                    computer.resume(); // stdin closed, nobody to talk to
+                    return true;
+                },
+            }
+        }
+    }
+
+    // blocks for commands while the machine is halted, false when the user quits
+    pub(crate) fn wait(&mut self, computer: &mut Computer) -> bool
+    {
+        while computer.halted()
+        {
+            self.report_halt(computer);
+            match self.lines.recv()
+            {
+                Ok(line) =>
+                {
+                    if !self.command(computer, &line)
+                    {
+                        return false;
+                    }
+                },
+                Err(_) => computer.resume(), // stdin closed, nobody to talk to
+            }
+        }
+        self.stopped = false;
+        true
+    }
+
+    fn report_halt(&mut self, computer: &mut Computer)
+    {
+        if computer.halted() && !self.stopped
+        {
+            if let Some((kind, address)) = computer.take_watch_hit()
+            {
+                println!("watchpoint {} {}", Self::kind_name(kind), computer.symbols().describe(address));
+            }
+            println!("stopped at {}", Self::instruction_line(computer, computer.pc()));
+            Self::prompt();
+        }
+        self.stopped = computer.halted();
+    }
+
+    fn prompt()
+    {
+        print!("> ");
+        let _ = io::stdout().flush();
+    }
+
+    fn parse_number(symbols: &Symbols, text: &str) -> Option<u32>
+    {
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+        {
+            return u32::from_str_radix(hex, 16).ok();
+        }
+        if text.starts_with(|c: char| c.is_ascii_digit())
+        {
+            return text.parse().ok();
+        }
+
+        // symbol or symbol+offset
+        match text.split_once('+')
+        {
+            Some((name, offset)) =>
+            {
+                let offset = Self::parse_number(&Symbols::default(), offset)?;
+                symbols.address_of(name)?.checked_add(offset)
+            },
+            None => symbols.address_of(text),
+        }
+    }
+
+    fn kind_name(kind: WatchKind) -> &'static str
+    {
+        match kind
+        {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::Access => "rw",
+            WatchKind::Execute => "x",
+        }
+    }
+
+    fn parse_kind(text: &str) -> Option<WatchKind>
+    {
+        match text
+        {
+            "r" => Some(WatchKind::Read),
+            "w" => Some(WatchKind::Write),
+            "rw" => Some(WatchKind::Access),
+            "x" => Some(WatchKind::Execute),
+            _ => None,
+        }
+    }
+
+    // wp, wd and wl, which take a kind besides numbers
+    fn watch_command(computer: &mut Computer, words: &[&str])
+    {
+        let symbols = computer.symbols();
+        let range = match words
+        {
+            [_, address, len, kind, ..] => Self::parse_number(&symbols, address)
+                .zip(Self::parse_number(&symbols, len))
+                .zip(Self::parse_kind(kind)),
+            _ => None,
+        };
+
+        match (words[0], range, words.get(4).copied())
+        {
+            ("wl", _, _) =>
+            {
+                for &(address, len, kind, action) in computer.watchpoints()
+                {
+                    let log = if action == WatchAction::Log { " log" } else { "" };
+                    println!("{} {len} {}{log}", symbols.describe(address), Self::kind_name(kind));
+                }
+            },
+            ("wp", Some(((address, len), kind)), None) =>
+                computer.add_watchpoint(address, len, kind, WatchAction::Halt),
+            ("wp", Some(((address, len), kind)), Some("log")) =>
+                computer.add_watchpoint(address, len, kind, WatchAction::Log),
+            ("wd", Some(((address, len), kind)), None) =>
+            {
+                if !computer.remove_watchpoint(address, len, kind)
+                {
+                    println!("no such watchpoint");
+                }
+            },
+            _ => println!("usage: wp ADDR LEN r|w|rw|x [log], wd ADDR LEN r|w|rw|x, wl"),
+        }
+    }
+
+    fn instruction_line(computer: &Computer, address: u32) -> String
+    {
+        match computer.read_memory(address, 4)
+        {
+            Some(bytes) =>
+            {
+                let instruction = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let symbols = computer.symbols();
+                let location = symbols.location(address).map(|location| format!(" <{location}>")).unwrap_or_default();
+                format!("{address:#010x}{location}: {instruction:08x}  {}",
+                        disassemble_with_symbols(instruction, address, &symbols))
+            },
+            None => format!("{address:#010x}: out of memory"),
+        }
+    }
+
+    fn command(&mut self, computer: &mut Computer, line: &str) -> bool
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied()
+        {
+            Some("wp") | Some("wd") | Some("wl") =>
+            {
+                Self::watch_command(computer, &words);
+                Self::prompt();
+                return true;
+            },
+            Some("t") =>
+            {
+                match computer.bus_trace()
+                {
+                    Some(trace) =>
+                    {
+                        let _ = trace.dump(&mut io::stdout());
+                    },
+                    None => println!("tracing is off, start with trace_buffer=N"),
+                }
+                Self::prompt();
+                return true;
+            },
+            _ => {},
+        }
+
+        let symbols = computer.symbols();
+        let numbers: Option<Vec<u32>> = words.iter().skip(1).map(|word| Self::parse_number(&symbols, word)).collect();
+        let numbers = match numbers
+        {
+            Some(numbers) => numbers,
+            None =>
+            {
+                println!("bad number");
+                Self::prompt();
+                return true;
+            },
+        };
+
+        match (words.first().copied(), numbers.as_slice())
+        {
+            (None, _) => {},
+            (Some("h") | Some("help"), _) => println!("{HELP}"),
+            (Some("q") | Some("quit"), _) => return false,
+            (Some("s") | Some("step"), args) =>
+            {
+                let count = args.first().copied().unwrap_or(1);
+                for _ in 0..count
+                {
+                    computer.step();
+                }
+                println!("{}", Self::instruction_line(computer, computer.pc()));
+            },
+            (Some("c") | Some("continue"), _) =>
+            {
+                computer.resume();
+                self.stopped = false;
+                return true; // no prompt while running
+            },
+            (Some("halt"), _) => computer.halt(),
+            (Some("b"), [address]) => computer.add_breakpoint(*address),
+            (Some("d"), [address]) =>
+            {
+                if !computer.remove_breakpoint(*address)
+                {
+                    println!("no breakpoint at {}", symbols.describe(*address));
+                }
+            },
+            (Some("bl"), _) =>
+            {
+                for address in computer.breakpoints()
+                {
+                    println!("{}", symbols.describe(address));
+                }
+            },
+            (Some("r") | Some("regs"), _) =>
+            {
+                let (registers, _) = computer.registers();
+                for row in 0..8
+                {
+                    let line: Vec<String> = (0..4)
+                        .map(|column| column * 8 + row)
+                        .map(|i| format!("{:>5} {:08x}", REGISTER_NAMES[i], registers[i] as u32))
+                        .collect();
+                    println!("{}", line.join("  "));
+                }
+                println!("   pc {:08x}     hi {:08x}     lo {:08x}", registers[32] as u32, registers[33] as u32,
+                         registers[34] as u32);
+            },
+            (Some("u") | Some("dis"), args) =>
+            {
+                let start = args.first().copied().unwrap_or(computer.pc().saturating_sub(16)) & !3;
+                let count = args.get(1).copied().unwrap_or(10);
+                for i in 0..count
+                {
+                    let address = start.wrapping_add(4 * i);
+                    let marker = if address == computer.pc() { "=>" } else { "  " };
+                    println!("{marker} {}", Self::instruction_line(computer, address));
+                }
+            },
+            (Some("x"), [address, args @ ..]) =>
+            {
+                let len = args.first().copied().unwrap_or(64);
+                match computer.read_memory(*address, len)
+                {
+                    Some(bytes) =>
+                    {
+                        for (i, chunk) in bytes.chunks(16).enumerate()
+                        {
+                            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+                            println!("{:#010x}: {}", address + 16 * i as u32, hex.join(" "));
+                        }
+                    },
+                    None => println!("out of memory"),
+                }
+            },
+            (Some("w"), [address, bytes @ ..]) if !bytes.is_empty() =>
+            {
+                if bytes.iter().any(|byte| *byte > 0xFF)
+                {
+                    println!("bad byte");
+                }
+                else
+                {
+                    let bytes: Vec<u8> = bytes.iter().map(|byte| *byte as u8).collect();
+                    if !computer.write_memory(*address, &bytes)
+                    {
+                        println!("out of memory");
+                    }
+                }
+            },
+            _ => println!("unknown command, h for help"),
+        }
+
+        Self::prompt();
+        true
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use computer::{Computer, Screen};
+
+// writes presented frames as an uncompressed YUV4MPEG2 stream, 4:4:4, BT.601 limited range
+pub(crate) struct Recorder
+{
+    out: BufWriter<File>,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    planes: Vec<u8>, // Y, Cb, Cr one after another
+}
+
+impl Recorder
+{
+    pub(crate) fn start(path: &Path, width: u32, height: u32, frame_rate: u32) -> io::Result<Recorder>
+    {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "YUV4MPEG2 W{width} H{height} F{frame_rate}:1 Ip A1:1 C444")?;
+
+        Ok(Recorder
+        {
+            out,
+            path: path.to_path_buf(),
+            width,
+            height,
+            rgba: vec![0; width as usize * 4],
+            planes: vec![0; (width * height) as usize * 3],
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    pub(crate) fn write_frame(&mut self, screen: &Screen) -> io::Result<()>
+    {
+        let plane_size = (self.width * self.height) as usize;
+
+        for y in 0..self.height
+        {
+            screen.row_to_rgba(y, &mut self.rgba);
+            for (x, pixel) in self.rgba.chunks_exact(4).enumerate()
+            {
+                let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+                let i = (y * self.width) as usize + x;
+                self.planes[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+                self.planes[plane_size + i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+                self.planes[2 * plane_size + i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+            }
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&self.planes)
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()>
+    {
+        self.out.flush()
+    }
+}
+
+pub(crate) fn start_recording(path: &Path, computer: &Computer, frame_rate: u32) -> Option<Recorder>
+{
+    let screen = computer.screen();
+    match Recorder::start(path, screen.width(), screen.height(), frame_rate)
+    {
+        Ok(recorder) => Some(recorder),
+        Err(error) =>
+        {
+            eprintln!("Cannot record to {}: {error}", path.display());
+            None
+        },
+    }
+}
+
+// a failed write ends the recording
+pub(crate) fn record_frame(recorder: &mut Option<Recorder>, computer: &Computer)
+{
+    if let Some(active) = recorder
+    {
+        if let Err(error) = active.write_frame(&computer.screen())
+        {
+            eprintln!("Recording to {} failed: {error}", active.path().display());
+            *recorder = None;
+        }
+    }
+}
+
+pub(crate) fn stop_recording(recorder: &mut Option<Recorder>)
+{
+    if let Some(active) = recorder.take()
+    {
+        let path = active.path().to_path_buf();
+        if let Err(error) = active.finish()
+        {
+            eprintln!("Recording to {} failed: {error}", path.display());
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// Full screen triangle, placed on the window by the viewport.
+
+struct VertexOutput {
+    [[location(0)]] tex_coord: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let x = select(-1.0, 3.0, vertex_index == 1u);
+    let y = select(-1.0, 3.0, vertex_index == 2u);
+
+    var out: VertexOutput;
+    out.tex_coord = vec2<f32>((x + 1.0) * 0.5, (1.0 - y) * 0.5);
+    out.position = vec4<f32>(x, y, 0.0, 1.0);
+    return out;
+}
+
+[[group(0), binding(0)]] var r_tex_color: texture_2d<f32>;
+[[group(0), binding(1)]] var r_tex_sampler: sampler;
+
+[[stage(fragment)]]
+fn fs_main([[location(0)]] tex_coord: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    return textureSample(r_tex_color, r_tex_sampler, tex_coord);
+}
@@ -0,0 +1,189 @@
+use computer_config::{Filter, Scaling};
+use pixels::wgpu;
+use pixels::Pixels;
+
+// Draws the guest frame into the window, letterboxed, with the configured scaling and filter.
+pub(crate) struct Scaler
+{
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+
+    scaling: Scaling,
+    texture_size: (u32, u32),
+    rect: (f32, f32, f32, f32), // x, y, width, height of the picture in the window
+}
+
+impl Scaler
+{
+    pub(crate) fn new(pixels: &Pixels, scaling: Scaling, filter: Filter, texture_size: (u32, u32),
+                      surface_size: (u32, u32)) -> Self
+    {
+        let device = pixels.device();
+
+        let filter = match filter
+        {
+            Filter::Nearest => wgpu::FilterMode::Nearest,
+            Filter::Linear => wgpu::FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor
+        {
+            label: Some("jupiter_scaler_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some("jupiter_scaler_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture
+                    {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let texture_view = pixels.texture().create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor
+        {
+            label: Some("jupiter_scaler_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry
+                {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry
+                {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let module = device.create_shader_module(&wgpu::include_wgsl!("scale.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: Some("jupiter_scaler_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("jupiter_scaler_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState
+            {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState
+                {
+                    format: pixels.render_texture_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+
+        let mut scaler = Scaler
+        {
+            bind_group,
+            pipeline,
+            scaling,
+            texture_size,
+            rect: (0.0, 0.0, 0.0, 0.0),
+        };
+        scaler.resize(surface_size.0, surface_size.1);
+        scaler
+    }
+
+    pub(crate) fn resize(&mut self, width: u32, height: u32)
+    {
+        let (surface_width, surface_height) = (width as f32, height as f32);
+        let (texture_width, texture_height) = (self.texture_size.0 as f32, self.texture_size.1 as f32);
+
+        let fit = (surface_width / texture_width).min(surface_height / texture_height);
+        let scale = match self.scaling
+        {
+            Scaling::Integer if fit >= 1.0 => fit.floor(),
+            _ => fit, // window smaller than the guest screen, shrink to fit
+        };
+
+        let (scaled_width, scaled_height) = (texture_width * scale, texture_height * scale);
+        let x = ((surface_width - scaled_width) / 2.0).floor();
+        let y = ((surface_height - scaled_height) / 2.0).floor();
+        self.rect = (x, y, scaled_width, scaled_height);
+    }
+
+    pub(crate) fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView)
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("jupiter_scaler_render_pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment
+            {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations
+                {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        let (x, y, width, height) = self.rect;
+        if width < 1.0 || height < 1.0
+        {
+            return; // minimized
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        pass.draw(0..3, 0..1);
+    }
+
+    // window position to guest pixel, clamped to the screen edges
+    pub(crate) fn window_pos_to_pixel(&self, position: (f64, f64)) -> (u32, u32)
+    {
+        let (x, y, width, height) = self.rect;
+        let pixel_x = ((position.0 as f32 - x) / width * self.texture_size.0 as f32).floor();
+        let pixel_y = ((position.1 as f32 - y) / height * self.texture_size.1 as f32).floor();
+
+        let pixel_x = pixel_x.max(0.0).min(self.texture_size.0 as f32 - 1.0);
+        let pixel_y = pixel_y.max(0.0).min(self.texture_size.1 as f32 - 1.0);
+        (pixel_x as u32, pixel_y as u32)
+    }
+}